@@ -0,0 +1,47 @@
+-- Tables as created by versions before migrations were introduced. IF NOT EXISTS lets existing economies adopt the migration history without recreating anything
+CREATE TABLE IF NOT EXISTS currencies(
+    currency_id BIGSERIAL NOT NULL,
+    currency_code TEXT NOT NULL UNIQUE,
+    currency_name TEXT NOT NULL,
+    state TEXT NOT NULL,
+    circulation BIGINT NOT NULL,
+    reserves BIGINT NOT NULL,
+    owner TEXT NOT NULL,
+    value DOUBLE PRECISION GENERATED ALWAYS AS (
+        CASE WHEN reserves <= 0 THEN 0
+             WHEN circulation <= 0 THEN 0
+             ELSE (
+                 CAST(reserves AS DOUBLE PRECISION) / CAST(circulation AS DOUBLE PRECISION)
+             )
+             END
+        ) STORED,
+    PRIMARY KEY (currency_id)
+);
+
+CREATE TABLE IF NOT EXISTS transactions(
+    transaction_id BIGSERIAL NOT NULL,
+    transaction_date TIMESTAMP WITHOUT TIME ZONE NOT NULL,
+    currency_id BIGINT NOT NULL,
+    delta_circulation BIGINT,
+    delta_reserves BIGINT,
+    initiator TEXT NOT NULL,
+    PRIMARY KEY (transaction_id),
+    FOREIGN KEY (currency_id) REFERENCES currencies(currency_id) ON DELETE CASCADE
+);
+
+CREATE TABLE IF NOT EXISTS records(
+    record_id BIGSERIAL NOT NULL,
+    record_date DATE NOT NULL,
+    currency_id BIGINT NOT NULL,
+    opening_value DOUBLE PRECISION,
+    closing_value DOUBLE PRECISION,
+    delta_value DOUBLE PRECISION GENERATED ALWAYS AS (closing_value - opening_value) STORED,
+    growth SMALLINT GENERATED ALWAYS AS (
+        CASE WHEN (closing_value - opening_value) = 0 THEN 0
+             WHEN (closing_value - opening_value) > 0 THEN 1
+             ELSE -1
+             END
+        ) STORED,
+    PRIMARY KEY (record_id),
+    FOREIGN KEY (currency_id) REFERENCES currencies(currency_id) ON DELETE CASCADE
+);
@@ -0,0 +1,2 @@
+ALTER TABLE transactions ADD COLUMN IF NOT EXISTS reverts_transaction_id BIGINT REFERENCES transactions(transaction_id) ON DELETE CASCADE;
+CREATE UNIQUE INDEX IF NOT EXISTS transactions_reverts_transaction_id_key ON transactions(reverts_transaction_id);
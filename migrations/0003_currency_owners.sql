@@ -0,0 +1,12 @@
+ALTER TABLE currencies ADD COLUMN IF NOT EXISTS owner_id BIGINT;
+
+CREATE TABLE IF NOT EXISTS currency_managers(
+    currency_id BIGINT NOT NULL,
+    user_id BIGINT NOT NULL,
+    can_reserve BOOLEAN NOT NULL DEFAULT FALSE,
+    can_circulation BOOLEAN NOT NULL DEFAULT FALSE,
+    can_metadata BOOLEAN NOT NULL DEFAULT FALSE,
+    can_delete BOOLEAN NOT NULL DEFAULT FALSE,
+    PRIMARY KEY (currency_id, user_id),
+    FOREIGN KEY (currency_id) REFERENCES currencies(currency_id) ON DELETE CASCADE
+);
@@ -0,0 +1,10 @@
+CREATE TABLE IF NOT EXISTS audit_log(
+    audit_id BIGSERIAL NOT NULL,
+    audit_date TIMESTAMP WITH TIME ZONE NOT NULL,
+    actor_id BIGINT NOT NULL,
+    actor_name TEXT NOT NULL,
+    action TEXT NOT NULL,
+    currency_id BIGINT,
+    details TEXT,
+    PRIMARY KEY (audit_id)
+);
@@ -0,0 +1,2 @@
+-- Databases recreated through /currency database have a DATE column here, others have TIMESTAMP. Both are stored as UTC
+ALTER TABLE transactions ALTER COLUMN transaction_date TYPE TIMESTAMP WITH TIME ZONE USING CAST(transaction_date AS TIMESTAMP) AT TIME ZONE 'UTC';
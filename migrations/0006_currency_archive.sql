@@ -0,0 +1,2 @@
+-- Deleted currencies are archived rather than removed, so their transactions and records survive until they are purged
+ALTER TABLE currencies ADD COLUMN IF NOT EXISTS archived_at TIMESTAMP WITH TIME ZONE;
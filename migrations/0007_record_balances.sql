@@ -0,0 +1,3 @@
+-- Circulation and reserves at closing, so charts can overlay them. NULL for records taken before this was stored
+ALTER TABLE records ADD COLUMN IF NOT EXISTS closing_circulation BIGINT;
+ALTER TABLE records ADD COLUMN IF NOT EXISTS closing_reserves BIGINT;
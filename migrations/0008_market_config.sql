@@ -0,0 +1,8 @@
+-- Trading hours per guild, in the guild's own timezone. trading_days is a bitmask with Monday as bit 0
+CREATE TABLE IF NOT EXISTS market_config(
+    guild_id BIGINT PRIMARY KEY,
+    open_time TIME NOT NULL DEFAULT '06:00',
+    close_time TIME NOT NULL DEFAULT '18:00',
+    timezone TEXT NOT NULL DEFAULT 'UTC',
+    trading_days SMALLINT NOT NULL DEFAULT 127
+);
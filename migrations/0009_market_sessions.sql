@@ -0,0 +1,22 @@
+-- Opening values are stored as soon as the market opens, so a restart during trading doesn't lose the day's records
+CREATE TABLE IF NOT EXISTS market_sessions(
+    session_id BIGSERIAL NOT NULL,
+    guild_id BIGINT NOT NULL,
+    record_date DATE NOT NULL,
+    opened_at TIMESTAMP WITH TIME ZONE NOT NULL,
+    closes_at TIMESTAMP WITH TIME ZONE NOT NULL,
+    closed_at TIMESTAMP WITH TIME ZONE,
+    PRIMARY KEY (session_id)
+);
+
+CREATE TABLE IF NOT EXISTS opening_snapshots(
+    session_id BIGINT NOT NULL,
+    currency_id BIGINT NOT NULL,
+    opening_value DOUBLE PRECISION NOT NULL,
+    PRIMARY KEY (session_id, currency_id),
+    FOREIGN KEY (session_id) REFERENCES market_sessions(session_id) ON DELETE CASCADE,
+    FOREIGN KEY (currency_id) REFERENCES currencies(currency_id) ON DELETE CASCADE
+);
+
+-- Records estimated for days the bot was offline, or closed late after a restart
+ALTER TABLE records ADD COLUMN IF NOT EXISTS interpolated BOOLEAN NOT NULL DEFAULT FALSE;
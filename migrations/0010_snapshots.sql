@@ -0,0 +1,20 @@
+-- Values through the trading day, taken at an interval and after every transaction, so daily highs and lows can be recorded
+CREATE TABLE IF NOT EXISTS snapshots(
+    snapshot_id BIGSERIAL NOT NULL,
+    currency_id BIGINT NOT NULL,
+    snapshot_date TIMESTAMP WITH TIME ZONE NOT NULL,
+    value DOUBLE PRECISION NOT NULL,
+    circulation BIGINT NOT NULL,
+    reserves BIGINT NOT NULL,
+    PRIMARY KEY (snapshot_id),
+    FOREIGN KEY (currency_id) REFERENCES currencies(currency_id) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS snapshots_currency_date ON snapshots(currency_id, snapshot_date);
+
+-- NULL for records taken before snapshots existed, and for interpolated records
+ALTER TABLE records ADD COLUMN IF NOT EXISTS high_value DOUBLE PRECISION;
+ALTER TABLE records ADD COLUMN IF NOT EXISTS low_value DOUBLE PRECISION;
+
+-- 0 turns off snapshots at an interval, leaving only those taken after transactions
+ALTER TABLE market_config ADD COLUMN IF NOT EXISTS snapshot_interval_minutes INTEGER NOT NULL DEFAULT 60;
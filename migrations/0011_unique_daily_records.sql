@@ -0,0 +1,7 @@
+-- Restarts and retries near the close could record a day twice. Keep the latest record of each day before enforcing one per day
+DELETE FROM records older USING records newer
+    WHERE older.currency_id = newer.currency_id
+    AND older.record_date = newer.record_date
+    AND older.record_id < newer.record_id;
+
+CREATE UNIQUE INDEX IF NOT EXISTS records_currency_date ON records(currency_id, record_date);
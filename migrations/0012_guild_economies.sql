@@ -0,0 +1,13 @@
+-- Every economy belongs to one Discord server. The migration can't know which server existing rows came from, so the bot adopts them into its own server (DISCORD_GUILD_ID) when it starts
+ALTER TABLE currencies ADD COLUMN IF NOT EXISTS guild_id BIGINT;
+ALTER TABLE transactions ADD COLUMN IF NOT EXISTS guild_id BIGINT;
+ALTER TABLE records ADD COLUMN IF NOT EXISTS guild_id BIGINT;
+ALTER TABLE audit_log ADD COLUMN IF NOT EXISTS guild_id BIGINT;
+
+-- Currency codes only need to be unique within a server
+ALTER TABLE currencies DROP CONSTRAINT IF EXISTS currencies_currency_code_key;
+CREATE UNIQUE INDEX IF NOT EXISTS currencies_guild_code ON currencies(guild_id, currency_code);
+
+CREATE INDEX IF NOT EXISTS transactions_guild ON transactions(guild_id);
+CREATE INDEX IF NOT EXISTS records_guild ON records(guild_id);
+CREATE INDEX IF NOT EXISTS audit_log_guild ON audit_log(guild_id);
@@ -0,0 +1,5 @@
+-- Per-guild settings that aren't about the market. Members with admin_role_id can use the bot's dangerous commands
+CREATE TABLE IF NOT EXISTS guild_settings(
+    guild_id BIGINT PRIMARY KEY,
+    admin_role_id BIGINT
+);
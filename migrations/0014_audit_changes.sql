@@ -0,0 +1,3 @@
+-- What an audited command changed, as the affected row before and after. Either is null when the row was created or removed
+ALTER TABLE audit_log ADD COLUMN IF NOT EXISTS before_data JSONB;
+ALTER TABLE audit_log ADD COLUMN IF NOT EXISTS after_data JSONB;
@@ -0,0 +1,2 @@
+-- Channel every transaction, currency change and daily summary is mirrored to, none to keep the ledger off
+ALTER TABLE guild_settings ADD COLUMN IF NOT EXISTS ledger_channel_id BIGINT;
@@ -0,0 +1,2 @@
+-- Channel the daily market report is posted to at closing, none to post it to the ledger channel instead
+ALTER TABLE guild_settings ADD COLUMN IF NOT EXISTS report_channel_id BIGINT;
@@ -0,0 +1,4 @@
+-- Who made a transaction by user ID, since names change. NULL for transactions made before this was stored
+ALTER TABLE transactions ADD COLUMN IF NOT EXISTS initiator_id BIGINT;
+
+CREATE INDEX IF NOT EXISTS transactions_initiator ON transactions(initiator_id);
@@ -0,0 +1,100 @@
+use crate::commands::manage::DBManager;
+use crate::commands::query::DBQueryAgent;
+use crate::types::*;
+use serenity::model::guild::Member;
+use serenity::model::prelude::interaction::application_command::ApplicationCommandInteraction;
+use serenity::model::user::User;
+use tracing::{error, warn};
+
+// Every handler that changes a currency goes through here, so permission checks and denials are handled the same way everywhere
+pub async fn authorize(query_agent: &DBQueryAgent, manager: &DBManager, currency_code: String, user: &User, permission: CurrencyPermission) -> Result<CurrencyData, String> {
+    let currency_data = match query_agent.get_currency_data(currency_code.clone()).await {
+        Ok(d) => d,
+        Err(_e) => return Err(format!("Error: could not find the currency code `{currency_code}`"))
+    };
+
+    check_permission(query_agent, manager, &currency_data, user, permission).await?;
+    Ok(currency_data)
+}
+
+// Only the owner can transfer a currency, except for currencies created before owners were stored by ID, which a server admin assigns an owner to
+pub async fn authorize_transfer(query_agent: &DBQueryAgent, manager: &DBManager, currency_code: String, data: &ApplicationCommandInteraction) -> Result<CurrencyData, String> {
+    let currency_data = match query_agent.get_currency_data(currency_code.clone()).await {
+        Ok(d) => d,
+        Err(_e) => return Err(format!("Error: could not find the currency code `{currency_code}`"))
+    };
+
+    if currency_data.owner_id.is_some() {
+        check_permission(query_agent, manager, &currency_data, &data.user, CurrencyPermission::Owner).await?;
+    } else if require_guild_admin(data).is_err() && require_admin_role(query_agent, data.member.as_ref(), &data.user).await.is_err() {
+        return Err(format!("Error: **{}** `{}` has no owner on record, so only someone with the Manage Server permission or the admin role can transfer it", currency_data.currency_name, currency_data.currency_code))
+    }
+
+    Ok(currency_data)
+}
+
+// For currencies that have already been looked up, e.g. archived ones which `authorize` can't find
+pub async fn check_permission(query_agent: &DBQueryAgent, manager: &DBManager, currency_data: &CurrencyData, user: &User, permission: CurrencyPermission) -> Result<(), String> {
+    let allowed = match query_agent.user_has_permission(currency_data, user, permission).await {
+        Ok(a) => a,
+        Err(e) => return Err(format!("An error occured while checking currency permissions: {e:?}"))
+    };
+
+    if !allowed {
+        warn!("Denied {permission:?} permission on `{}` to {} ({})", currency_data.currency_code, user.name, user.id);
+        if let Err(e) = manager.log_audit_event(user, "permission-denied", Some(currency_data.currency_id), format!("{permission:?}")).await {
+            error!("Couldn't write audit log entry for permission denial: {e:?}");
+        }
+
+        if currency_data.owner_id.is_none() {
+            return Err(format!("Error: **{}** `{}` has no owner on record. Someone with the Manage Server permission or the admin role needs to assign one with `/currency owners transfer`", currency_data.currency_name, currency_data.currency_code))
+        }
+        return Err(format!("Error: you don't have permission to {} **{}** `{}`", describe_permission(permission), currency_data.currency_name, currency_data.currency_code))
+    }
+
+    Ok(())
+}
+
+// Bot-wide settings can only be changed by members who can manage the server
+pub fn require_guild_admin(data: &ApplicationCommandInteraction) -> Result<(), String> {
+    let is_admin = data.member.as_ref()
+        .and_then(|member| member.permissions)
+        .is_some_and(|permissions| permissions.manage_guild());
+
+    if !is_admin {
+        warn!("Denied guild admin command `{}` to {} ({})", data.data.name, data.user.name, data.user.id);
+        return Err("Error: you need the Manage Server permission to do this".into())
+    }
+
+    Ok(())
+}
+
+// Dangerous commands need the guild's admin role, and are disabled until one is set
+pub async fn require_admin_role(query_agent: &DBQueryAgent, member: Option<&Member>, user: &User) -> Result<(), String> {
+    let settings = match query_agent.get_guild_settings().await {
+        Ok(s) => s,
+        Err(e) => return Err(format!("An error occured while checking the admin role: {e:?}"))
+    };
+
+    let Some(admin_role_id) = settings.admin_role_id else {
+        return Err("Error: this server has no admin role set. Someone with the Manage Server permission can set one with `/currency admin`".into())
+    };
+
+    let has_role = member.is_some_and(|member| member.roles.iter().any(|role| role.0 as i64 == admin_role_id));
+    if !has_role {
+        warn!("Denied admin role command to {} ({})", user.name, user.id);
+        return Err(format!("Error: you need the <@&{admin_role_id}> role to do this"))
+    }
+
+    Ok(())
+}
+
+fn describe_permission(permission: CurrencyPermission) -> &'static str {
+    match permission {
+        CurrencyPermission::Reserve => "manage the gold reserves of",
+        CurrencyPermission::Circulation => "manage the circulation of",
+        CurrencyPermission::Metadata => "modify",
+        CurrencyPermission::Delete => "delete",
+        CurrencyPermission::Owner => "manage the ownership and transactions of"
+    }
+}
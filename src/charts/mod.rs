@@ -0,0 +1,531 @@
+use crate::types::*;
+use anyhow::anyhow;
+use chrono::{Duration, NaiveDateTime};
+use image::{ColorType, ImageEncoder};
+use image::codecs::png::PngEncoder;
+use plotters::coord::types::RangedCoordf64;
+use plotters::prelude::*;
+use plotters::style::colors::full_palette::*;
+
+const CHART_SIZE: (u32, u32) = (1024, 768);
+
+// The daily market report's chart sits under a lot of text, so it's kept small
+const REPORT_CHART_SIZE: (u32, u32) = (800, 400);
+
+type TrendChart<'a, 'b> = ChartContext<'a, BitMapBackend<'b>, Cartesian2d<RangedDateTime<NaiveDateTime>, RangedCoordf64>>;
+
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub enum ChartKind {
+    #[default]
+    Line, // Closing value
+    Candle, // Opening to closing value
+    Bar, // Change in value over the day
+}
+
+// Balances plotted against a secondary axis on the right
+#[derive(Copy, Clone, Debug, Default)]
+pub struct ChartOverlays {
+    pub circulation: bool,
+    pub reserves: bool,
+}
+
+impl ChartOverlays {
+    pub fn any(&self) -> bool {
+        self.circulation || self.reserves
+    }
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub enum ChartTheme {
+    #[default]
+    Dark,
+    Light,
+}
+
+struct Palette {
+    background: RGBColor,
+    foreground: RGBColor, // Captions, labels and axes
+    neutral: RGBColor, // Line colour when the value hasn't moved much
+    rise: RGBColor,
+    fall: RGBColor,
+    circulation: RGBColor,
+    reserves: RGBColor,
+    series: [RGBColor; 5], // One per currency in a comparison, in the order they were given
+}
+
+const DARK_PALETTE: Palette = Palette {
+    background: RGBColor(56, 58, 64),
+    foreground: GREY_50,
+    neutral: BLUEGREY_A100,
+    rise: LIME_A700,
+    fall: RED_600,
+    circulation: LIGHTBLUE_A200,
+    reserves: AMBER_A400,
+    series: [LIGHTBLUE_A200, AMBER_A400, LIME_A700, PINK_A200, DEEPPURPLE_A100],
+};
+
+const LIGHT_PALETTE: Palette = Palette {
+    background: RGBColor(255, 255, 255),
+    foreground: GREY_900,
+    neutral: BLUEGREY_700,
+    rise: GREEN_700,
+    fall: RED_700,
+    circulation: BLUE_700,
+    reserves: AMBER_800,
+    series: [BLUE_700, ORANGE_800, GREEN_700, PINK_600, DEEPPURPLE_500],
+};
+
+impl ChartTheme {
+    fn palette(&self) -> &'static Palette {
+        match self {
+            ChartTheme::Dark => &DARK_PALETTE,
+            ChartTheme::Light => &LIGHT_PALETTE
+        }
+    }
+}
+
+// Builds a PNG of a currency's records. `records` are newest first, as returned by `DBQueryAgent::get_reports`
+pub struct CurrencyChart<'a> {
+    currency: &'a CurrencyData,
+    records: &'a [RecordData],
+    kind: ChartKind,
+    overlays: ChartOverlays,
+    theme: ChartTheme,
+}
+
+impl<'a> CurrencyChart<'a> {
+    pub fn new(currency: &'a CurrencyData, records: &'a [RecordData]) -> Self {
+        CurrencyChart {
+            currency,
+            records,
+            kind: ChartKind::default(),
+            overlays: ChartOverlays::default(),
+            theme: ChartTheme::default()
+        }
+    }
+
+    pub fn kind(mut self, kind: ChartKind) -> Self {
+        self.kind = kind;
+        self
+    }
+
+    pub fn overlays(mut self, overlays: ChartOverlays) -> Self {
+        self.overlays = overlays;
+        self
+    }
+
+    pub fn theme(mut self, theme: ChartTheme) -> Self {
+        self.theme = theme;
+        self
+    }
+
+    pub fn render(&self) -> anyhow::Result<Vec<u8>> {
+        render_trend(self.currency, self.records, self.kind, self.overlays, self.theme)
+    }
+}
+
+fn render_trend(currency: &CurrencyData, records: &[RecordData], kind: ChartKind, overlays: ChartOverlays, theme: ChartTheme) -> anyhow::Result<Vec<u8>> {
+    let palette = theme.palette();
+    let latest_data = records.first().ok_or_else(|| anyhow!("No records to plot"))?;
+    let earliest_data = &records[records.len() - 1];
+
+    let (width, height) = CHART_SIZE;
+    let mut buffer = vec![0u8; (width * height * 3) as usize];
+
+    {
+        let root = BitMapBackend::with_buffer(&mut buffer, CHART_SIZE).into_drawing_area();
+        root.fill(&palette.background)?;
+
+        // Records are daily, so half a day either side keeps candles and bars from being cut off at the edges
+        let x_range = (date_time(earliest_data) - Duration::hours(12))..(date_time(latest_data) + Duration::hours(12));
+
+        let mut chart = ChartBuilder::on(&root)
+            .margin(10)
+            .caption(format!("Currency trend for {}", currency.currency_name), ("sans-serif", 40, &palette.foreground))
+            .set_label_area_size(LabelAreaPosition::Left, 60)
+            .set_label_area_size(LabelAreaPosition::Right, if overlays.any() { 90 } else { 60 })
+            .set_label_area_size(LabelAreaPosition::Bottom, 40)
+            .build_cartesian_2d(RangedDateTime::from(x_range.clone()), y_range(records, kind))?;
+
+        if overlays.any() {
+            let overlay_max = records.iter()
+                .flat_map(|record| [
+                    record.closing_circulation.filter(|_| overlays.circulation),
+                    record.closing_reserves.filter(|_| overlays.reserves)
+                ])
+                .flatten()
+                .max()
+                .unwrap_or(0) as f64;
+
+            let mut chart = chart.set_secondary_coord(RangedDateTime::from(x_range), 0f64..(overlay_max * 1.1).max(1.0));
+            draw_primary(&mut chart, currency, records, kind, palette)?;
+
+            chart
+                .configure_secondary_axes()
+                .y_desc("Circulation / gold reserves")
+                .axis_desc_style(("sans-serif", 30, &palette.foreground))
+                .label_style(("sans-serif", 20, &palette.foreground))
+                .y_label_formatter(&|amount| format!("{amount:.0}"))
+                .axis_style(palette.foreground)
+                .draw()?;
+
+            if overlays.circulation {
+                chart.draw_secondary_series(LineSeries::new(
+                    records.iter().filter_map(|record| record.closing_circulation.map(|amount| (date_time(record), amount as f64))),
+                    palette.circulation.stroke_width(2)
+                ))?
+                    .label(format!("Circulation ({})", currency.currency_code))
+                    .legend(|(x, y)| PathElement::new(vec![(x, y), (x + 20, y)], palette.circulation.stroke_width(2)));
+            }
+            if overlays.reserves {
+                chart.draw_secondary_series(LineSeries::new(
+                    records.iter().filter_map(|record| record.closing_reserves.map(|amount| (date_time(record), amount as f64))),
+                    palette.reserves.stroke_width(2)
+                ))?
+                    .label("Gold reserves (ingots)")
+                    .legend(|(x, y)| PathElement::new(vec![(x, y), (x + 20, y)], palette.reserves.stroke_width(2)));
+            }
+
+            chart
+                .configure_series_labels()
+                .background_style(palette.background.mix(0.8))
+                .border_style(palette.foreground)
+                .label_font(("sans-serif", 20, &palette.foreground))
+                .position(SeriesLabelPosition::UpperLeft)
+                .draw()?;
+        } else {
+            draw_primary(&mut chart, currency, records, kind, palette)?;
+        }
+
+        root.present()?;
+    }
+
+    let mut png = vec![];
+    PngEncoder::new(&mut png).write_image(&buffer, width, height, ColorType::Rgb8)?;
+    Ok(png)
+}
+
+// Plots the closing values of several currencies on shared axes. `normalise` rebases every currency to 100 at the first date they all have a record for
+pub fn render_comparison(series: &[(CurrencyData, Vec<RecordData>)], normalise: bool, theme: ChartTheme) -> anyhow::Result<Vec<u8>> {
+    let codes: Vec<&str> = series.iter().map(|(currency, _)| currency.currency_code.as_str()).collect();
+    draw_comparison(series, normalise, theme, CHART_SIZE, format!("Currency comparison: {}", codes.join(", ")))
+}
+
+// The day's biggest movers over the last few days, rebased so they can share an axis
+pub fn render_market_report(series: &[(CurrencyData, Vec<RecordData>)], theme: ChartTheme) -> anyhow::Result<Vec<u8>> {
+    draw_comparison(series, true, theme, REPORT_CHART_SIZE, "Biggest movers".into())
+}
+
+fn draw_comparison(series: &[(CurrencyData, Vec<RecordData>)], normalise: bool, theme: ChartTheme, size: (u32, u32), caption: String) -> anyhow::Result<Vec<u8>> {
+    let palette = theme.palette();
+    if series.is_empty() || series.len() > palette.series.len() {
+        return Err(anyhow!("Can only compare between 1 and {} currencies", palette.series.len()))
+    }
+
+    let mut points: Vec<(&CurrencyData, Vec<(NaiveDateTime, f64)>)> = vec![];
+    if normalise {
+        let common_date = series.iter()
+            .map(|(_, records)| records.iter().map(|record| record.record_date).min())
+            .collect::<Option<Vec<_>>>()
+            .and_then(|first_dates| first_dates.into_iter().max())
+            .ok_or_else(|| anyhow!("Every currency needs records to normalise values"))?;
+
+        for (currency, records) in series {
+            let base = records.iter()
+                .filter(|record| record.record_date >= common_date)
+                .min_by_key(|record| record.record_date)
+                .map(|record| record.closing_value)
+                .filter(|value| *value > 0.0)
+                .ok_or_else(|| anyhow!("{} has no value on or after {common_date} to normalise against", currency.currency_name))?;
+
+            points.push((currency, records.iter()
+                .filter(|record| record.record_date >= common_date)
+                .map(|record| (date_time(record), record.closing_value / base * 100.0))
+                .collect()));
+        }
+    } else {
+        for (currency, records) in series {
+            points.push((currency, records.iter().map(|record| (date_time(record), record.closing_value)).collect()));
+        }
+    }
+
+    let all_points = points.iter().flat_map(|(_, currency_points)| currency_points.iter());
+    let (from_date, to_date, min_value, max_value) = all_points.fold(
+        (NaiveDateTime::MAX, NaiveDateTime::MIN, f64::MAX, f64::MIN),
+        |(from, to, min, max), (date, value)| (from.min(*date), to.max(*date), min.min(*value), max.max(*value))
+    );
+    if from_date > to_date {
+        return Err(anyhow!("No records to plot"))
+    }
+    let y_range = if normalise {
+        let padding = ((max_value - min_value) * 0.1).max(1.0);
+        (min_value - padding)..(max_value + padding)
+    } else {
+        0.0..(max_value + 1.0)
+    };
+
+    let (width, height) = size;
+    let mut buffer = vec![0u8; (width * height * 3) as usize];
+
+    {
+        let root = BitMapBackend::with_buffer(&mut buffer, size).into_drawing_area();
+        root.fill(&palette.background)?;
+
+        let mut chart = ChartBuilder::on(&root)
+            .margin(10)
+            .caption(caption, ("sans-serif", 40, &palette.foreground))
+            .set_label_area_size(LabelAreaPosition::Left, 80)
+            .set_label_area_size(LabelAreaPosition::Bottom, 40)
+            .build_cartesian_2d(RangedDateTime::from((from_date - Duration::hours(12))..(to_date + Duration::hours(12))), y_range)?;
+
+        chart
+            .configure_mesh()
+            .disable_x_mesh()
+            .disable_y_mesh()
+            .x_labels(6)
+            .x_label_formatter(&|date| date.format("%Y-%m-%d").to_string())
+            .max_light_lines(4)
+            .y_desc(if normalise { "Value (first common date = 100)" } else { "Currency value (gold ingots per unit)" })
+            .y_label_formatter(&|value| if normalise { format!("{value:.0}") } else { format!("{value:.2}") })
+            .axis_desc_style(("sans-serif", 30, &palette.foreground))
+            .x_label_style(("sans-serif", 20, &palette.foreground))
+            .y_label_style(("sans-serif", 20, &palette.foreground))
+            .axis_style(palette.foreground)
+            .draw()?;
+
+        for ((currency, currency_points), color) in points.into_iter().zip(palette.series) {
+            chart.draw_series(LineSeries::new(currency_points, color.stroke_width(2)))?
+                .label(format!("{} ({})", currency.currency_name, currency.currency_code))
+                .legend(move |(x, y)| PathElement::new(vec![(x, y), (x + 20, y)], color.stroke_width(2)));
+        }
+
+        chart
+            .configure_series_labels()
+            .background_style(palette.background.mix(0.8))
+            .border_style(palette.foreground)
+            .label_font(("sans-serif", 20, &palette.foreground))
+            .position(SeriesLabelPosition::UpperLeft)
+            .draw()?;
+
+        root.present()?;
+    }
+
+    let mut png = vec![];
+    PngEncoder::new(&mut png).write_image(&buffer, width, height, ColorType::Rgb8)?;
+    Ok(png)
+}
+
+fn draw_primary(chart: &mut TrendChart, currency: &CurrencyData, records: &[RecordData], kind: ChartKind, palette: &'static Palette) -> anyhow::Result<()> {
+    let y_desc = match kind {
+        ChartKind::Bar => format!("Daily change in value (gold ingots per {})", currency.currency_code),
+        _ => format!("Currency value (gold ingots per {})", currency.currency_code)
+    };
+
+    chart
+        .configure_mesh()
+        .disable_x_mesh()
+        .disable_y_mesh()
+        .x_labels(6)
+        .x_label_formatter(&|date| date.format("%Y-%m-%d").to_string())
+        .max_light_lines(4)
+        .y_desc(y_desc)
+        .axis_desc_style(("sans-serif", 30, &palette.foreground))
+        .x_label_style(("sans-serif", 20, &palette.foreground))
+        .y_label_style(("sans-serif", 20, &palette.foreground))
+        .axis_style(palette.foreground)
+        .draw()?;
+
+    match kind {
+        ChartKind::Line => {
+            let graph_color = match (records.first(), records.get(1)) {
+                (Some(latest), Some(prev)) if latest.closing_value - prev.closing_value > 0.2 => &palette.rise,
+                (Some(latest), Some(prev)) if latest.closing_value - prev.closing_value < -0.2 => &palette.fall,
+                _ => &palette.neutral
+            };
+
+            // Shade each day's range between its high and low, where snapshots were taken
+            if records.iter().any(|record| record.high_value.is_some()) {
+                let band = records.iter().map(|record| (date_time(record), record.high()))
+                    .chain(records.iter().rev().map(|record| (date_time(record), record.low())))
+                    .collect::<Vec<_>>();
+                chart.draw_series(std::iter::once(Polygon::new(band, graph_color.mix(0.2).filled())))?;
+            }
+
+            chart.draw_series(LineSeries::new(
+                records.iter().map(|record| (date_time(record), record.closing_value)),
+                graph_color
+            ))?
+                .label("Value")
+                .legend(move |(x, y)| PathElement::new(vec![(x, y), (x + 20, y)], graph_color));
+        },
+        ChartKind::Candle => {
+            let candle_width = (chart.plotting_area().dim_in_pixel().0 as f64 / records.len() as f64 * 0.6) as u32;
+            chart.draw_series(records.iter().map(|record| {
+                CandleStick::new(
+                    date_time(record),
+                    record.opening_value,
+                    record.high(),
+                    record.low(),
+                    record.closing_value,
+                    palette.rise.filled(),
+                    palette.fall.filled(),
+                    candle_width.max(1)
+                )
+            }))?;
+        },
+        ChartKind::Bar => {
+            chart.draw_series(records.iter().map(|record| {
+                let color = if record.delta_value >= 0.0 { palette.rise } else { palette.fall };
+                Rectangle::new(
+                    [(date_time(record) - Duration::hours(8), 0.0), (date_time(record) + Duration::hours(8), record.delta_value)],
+                    color.filled()
+                )
+            }))?;
+        }
+    }
+
+    Ok(())
+}
+
+fn y_range(records: &[RecordData], kind: ChartKind) -> std::ops::Range<f64> {
+    match kind {
+        ChartKind::Line => {
+            let max_value = records.iter().map(|record| record.high()).fold(0.0, f64::max);
+            0.0..(max_value + 1.0)
+        },
+        ChartKind::Candle => {
+            let values = records.iter().flat_map(|record| [record.high(), record.low()]);
+            let (min_value, max_value) = values.fold((f64::MAX, f64::MIN), |(min, max), value| (min.min(value), max.max(value)));
+            let padding = ((max_value - min_value) * 0.1).max(0.1);
+            (min_value - padding).max(0.0)..(max_value + padding)
+        },
+        ChartKind::Bar => {
+            let (min_delta, max_delta) = records.iter().fold((0.0, 0.0), |(min, max): (f64, f64), record| (min.min(record.delta_value), max.max(record.delta_value)));
+            let padding = ((max_delta - min_delta) * 0.1).max(0.1);
+            (min_delta - padding)..(max_delta + padding)
+        }
+    }
+}
+
+fn date_time(record: &RecordData) -> NaiveDateTime {
+    record.record_date.and_hms_opt(0, 0, 0).unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::NaiveDate;
+    use image::{GenericImageView, Rgba};
+
+    fn currency(code: &str) -> CurrencyData {
+        CurrencyData {
+            currency_id: 1,
+            currency_name: format!("Test {code}"),
+            currency_code: code.into(),
+            circulation: 1000,
+            reserves: 250,
+            owner: "tester".into(),
+            owner_id: None,
+            value: 0.25,
+            state: "Testland".into(),
+            archived_at: None
+        }
+    }
+
+    // Newest first, like `get_reports`
+    fn records(days: u32, first_day: u32) -> Vec<RecordData> {
+        (first_day..first_day + days).rev().map(|day| {
+            let opening_value = 1.0 + (day % 3) as f64 * 0.5;
+            let closing_value = 1.0 + ((day + 1) % 3) as f64 * 0.5;
+            RecordData {
+                record_id: day as i64,
+                record_date: NaiveDate::from_ymd_opt(2024, 1, day).unwrap(),
+                currency_id: 1,
+                opening_value,
+                closing_value,
+                delta_value: closing_value - opening_value,
+                growth: 0,
+                closing_circulation: Some(1000 + day as i64 * 10),
+                closing_reserves: Some(250 + day as i64),
+                interpolated: false,
+                high_value: Some(opening_value.max(closing_value) + 0.25),
+                low_value: Some(opening_value.min(closing_value) - 0.25)
+            }
+        }).collect()
+    }
+
+    fn decode(png: &[u8]) -> image::DynamicImage {
+        image::load_from_memory_with_format(png, image::ImageFormat::Png).expect("chart should be a valid PNG")
+    }
+
+    #[test]
+    fn renders_every_kind() {
+        let currency = currency("TST");
+        let records = records(10, 1);
+        for kind in [ChartKind::Line, ChartKind::Candle, ChartKind::Bar] {
+            let png = CurrencyChart::new(&currency, &records).kind(kind).render().unwrap();
+            assert_eq!(decode(&png).dimensions(), CHART_SIZE, "{kind:?} chart has the wrong size");
+        }
+    }
+
+    #[test]
+    fn renders_overlays() {
+        let currency = currency("TST");
+        let records = records(10, 1);
+        let overlays = ChartOverlays { circulation: true, reserves: true };
+        let png = CurrencyChart::new(&currency, &records).overlays(overlays).render().unwrap();
+        assert_eq!(decode(&png).dimensions(), CHART_SIZE);
+    }
+
+    #[test]
+    fn renders_single_record() {
+        let currency = currency("TST");
+        let records = records(1, 1);
+        assert!(CurrencyChart::new(&currency, &records).kind(ChartKind::Candle).render().is_ok());
+    }
+
+    #[test]
+    fn empty_records_are_an_error() {
+        let currency = currency("TST");
+        assert!(CurrencyChart::new(&currency, &[]).render().is_err());
+    }
+
+    #[test]
+    fn themes_set_the_background() {
+        let currency = currency("TST");
+        let records = records(5, 1);
+        for theme in [ChartTheme::Dark, ChartTheme::Light] {
+            let png = CurrencyChart::new(&currency, &records).theme(theme).render().unwrap();
+            let RGBColor(r, g, b) = theme.palette().background;
+            assert_eq!(decode(&png).get_pixel(0, 0), Rgba([r, g, b, 255]), "{theme:?} background is wrong");
+        }
+    }
+
+    #[test]
+    fn renders_comparison() {
+        let series = vec![(currency("AAA"), records(10, 1)), (currency("BBB"), records(5, 6))];
+        for normalise in [false, true] {
+            let png = render_comparison(&series, normalise, ChartTheme::Light).unwrap();
+            assert_eq!(decode(&png).dimensions(), CHART_SIZE);
+        }
+    }
+
+    #[test]
+    fn renders_market_report() {
+        let series = vec![(currency("AAA"), records(7, 1)), (currency("BBB"), records(7, 2))];
+        let png = render_market_report(&series, ChartTheme::Dark).unwrap();
+        assert_eq!(decode(&png).dimensions(), REPORT_CHART_SIZE);
+    }
+
+    #[test]
+    fn comparison_rejects_too_many_currencies() {
+        let series: Vec<_> = ["AAA", "BBB", "CCC", "DDD", "EEE", "FFF"].iter().map(|code| (currency(code), records(3, 1))).collect();
+        assert!(render_comparison(&series, false, ChartTheme::Dark).is_err());
+    }
+
+    #[test]
+    fn normalising_needs_records_for_every_currency() {
+        let series = vec![(currency("AAA"), records(3, 1)), (currency("BBB"), vec![])];
+        assert!(render_comparison(&series, true, ChartTheme::Dark).is_err());
+    }
+}
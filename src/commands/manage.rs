@@ -1,10 +1,23 @@
 use crate::types::*;
-use sqlx::{Row, postgres::PgPool};
-use chrono::offset::Utc;
+use crate::commands::query::TransactionKind;
+use sqlx::{Row, QueryBuilder, Transaction, postgres::{PgPool, Postgres}};
+use chrono::{offset::Utc, DateTime, NaiveDate};
+use serenity::model::user::User;
+use std::collections::HashMap;
+use serde::Serialize;
+use serde_json::{json, Value};
 
+// Rows per batched INSERT, keeping well under Postgres' limit on bind parameters
+const INSERT_BATCH_SIZE: usize = 1000;
+
+// Snapshots are kept for this many of the most recent closed sessions, so days further back can't be rebuilt from them
+pub const SNAPSHOT_RETENTION_SESSIONS: i64 = 30;
+
+// Only ever changes the economy of one server
 #[derive(Clone)]
 pub struct DBManager {
-    pool: PgPool
+    pool: PgPool,
+    guild_id: i64
 }
 
 pub enum ModifyMetaType {
@@ -13,242 +26,681 @@ pub enum ModifyMetaType {
     State
 }
 
+// Why a transaction couldn't be reverted. The business rules are kept apart from database errors so they can be shown to users as they are
+#[derive(Debug)]
+pub enum RevertError {
+    IsReversal(i64), // ID of the transaction it reverts
+    AlreadyReverted(i64), // ID of the reversal
+    Db(sqlx::Error)
+}
+
+impl From<sqlx::Error> for RevertError {
+    fn from(e: sqlx::Error) -> Self {
+        RevertError::Db(e)
+    }
+}
+
 impl DBManager {
-    pub fn new(pool: PgPool) -> Self {
+    pub fn new(pool: PgPool, guild_id: i64) -> Self {
         DBManager {
-            pool
+            pool,
+            guild_id
         }
     }
 
-    pub async fn add_currency(&self, currency_code: String, currency_name: String, circulation: i64, gold_reserve: i64, state: String, owner: String) -> Result<CurrencyData, sqlx::Error> {
-        match sqlx::query("INSERT INTO currencies(currency_code, currency_name, circulation, reserves, state, owner) VALUES ($1, $2, $3, $4, $5, $6) RETURNING currency_id;")
-            .bind(currency_code.clone())
-            .bind(currency_name.clone())
+    // Rows from before economies were kept per server belong to the bot's own server
+    pub async fn adopt_legacy_rows(&self) -> Result<u64, sqlx::Error> {
+        let mut tx = self.pool.begin().await?;
+        let mut adopted = 0;
+        for table in ["currencies", "transactions", "records", "audit_log"] {
+            adopted += sqlx::query(format!("UPDATE {table} SET guild_id = $1 WHERE guild_id IS NULL").as_str())
+                .bind(self.guild_id)
+                .execute(&mut tx).await?
+                .rows_affected();
+        }
+        tx.commit().await?;
+        Ok(adopted)
+    }
+
+    pub async fn add_currency(&self, currency_code: String, currency_name: String, circulation: i64, gold_reserve: i64, state: String, owner: &User) -> Result<CurrencyData, sqlx::Error> {
+        let mut tx = self.pool.begin().await?;
+
+        let currency_data: CurrencyData = sqlx::query_as("INSERT INTO currencies(guild_id, currency_code, currency_name, circulation, reserves, state, owner, owner_id) VALUES ($1, $2, $3, $4, $5, $6, $7, $8) RETURNING *")
+            .bind(self.guild_id)
+            .bind(currency_code)
+            .bind(currency_name)
             .bind(circulation)
             .bind(gold_reserve)
-            .bind(state.clone())
-            .bind(owner.clone())
-            .fetch_one(&self.pool).await {
-                Ok(row) => {
-                    let currency_id = row.try_get("currency_id")?;
-                    Ok(CurrencyData {
-                        currency_id,
-                        currency_name,
-                        currency_code,
-                        circulation,
-                        value: gold_reserve as f64 / circulation as f64,
-                        reserves: gold_reserve,
-                        state,
-                        owner
-                    })   
-                },
-                Err(e) => Err(e)
-            }
+            .bind(state)
+            .bind(owner.name.clone())
+            .bind(owner.id.0 as i64)
+            .fetch_one(&mut tx).await?;
+
+        self.audit(&mut tx, owner, "create", Some(currency_data.currency_id), None, to_json(&currency_data)).await?;
+        tx.commit().await?;
+        Ok(currency_data)
     }
 
-    pub async fn remove_currency(&self, currency_code: String) -> Result<(), sqlx::Error> {
-        match sqlx::query("DELETE FROM currencies WHERE currency_code = $1;")
+    pub async fn archive_currency(&self, currency_code: String, actor: &User) -> Result<CurrencyData, sqlx::Error> {
+        let mut tx = self.pool.begin().await?;
+
+        let before: CurrencyData = sqlx::query_as("SELECT * FROM currencies WHERE guild_id = $1 AND currency_code = $2 AND archived_at IS NULL FOR UPDATE")
+            .bind(self.guild_id)
             .bind(currency_code)
-            .execute(&self.pool)
-            .await {
-                Ok(_) => Ok(()),
-                Err(e) => return Err(e)    
-            }
+            .fetch_one(&mut tx).await?;
+
+        let after: CurrencyData = sqlx::query_as("UPDATE currencies SET archived_at = $1 WHERE currency_id = $2 RETURNING *")
+            .bind(Utc::now())
+            .bind(before.currency_id)
+            .fetch_one(&mut tx).await?;
+
+        self.audit(&mut tx, actor, "delete", Some(after.currency_id), to_json(&before), to_json(&after)).await?;
+        tx.commit().await?;
+        Ok(after)
     }
 
-    pub async fn reserve_modify(&self, currency_code: String, amount: i64, initiator: String) -> Result<TransactionData, sqlx::Error> {
-        let currency_data: CurrencyData = match sqlx::query_as("SELECT * FROM currencies WHERE currency_code = $1")
+    pub async fn restore_currency(&self, currency_id: i64, actor: &User) -> Result<CurrencyData, sqlx::Error> {
+        let mut tx = self.pool.begin().await?;
+
+        let before: CurrencyData = sqlx::query_as("SELECT * FROM currencies WHERE guild_id = $1 AND currency_id = $2 AND archived_at IS NOT NULL FOR UPDATE")
+            .bind(self.guild_id)
+            .bind(currency_id)
+            .fetch_one(&mut tx).await?;
+
+        let after: CurrencyData = sqlx::query_as("UPDATE currencies SET archived_at = NULL WHERE currency_id = $1 RETURNING *")
+            .bind(currency_id)
+            .fetch_one(&mut tx).await?;
+
+        self.audit(&mut tx, actor, "restore", Some(currency_id), to_json(&before), to_json(&after)).await?;
+        tx.commit().await?;
+        Ok(after)
+    }
+
+    // Permanently deletes currencies archived before the cutoff, along with their transactions and records
+    pub async fn purge_archived_currencies(&self, archived_before: DateTime<Utc>) -> Result<Vec<CurrencyData>, sqlx::Error> {
+        sqlx::query_as("DELETE FROM currencies WHERE guild_id = $1 AND archived_at < $2 RETURNING *")
+            .bind(self.guild_id)
+            .bind(archived_before)
+            .fetch_all(&self.pool)
+            .await
+    }
+
+    pub async fn reserve_modify(&self, currency_code: String, amount: i64, initiator: &User) -> Result<(TransactionData, CurrencyData), sqlx::Error> {
+        self.balance_modify(currency_code, TransactionKind::Reserve, amount, initiator).await
+    }
+
+    pub async fn circulation_modify(&self, currency_code: String, amount: i64, initiator: &User) -> Result<(TransactionData, CurrencyData), sqlx::Error> {
+        self.balance_modify(currency_code, TransactionKind::Circulation, amount, initiator).await
+    }
+
+    async fn balance_modify(&self, currency_code: String, kind: TransactionKind, amount: i64, initiator: &User) -> Result<(TransactionData, CurrencyData), sqlx::Error> {
+        let mut tx = self.pool.begin().await?;
+
+        // Apply the delta in the database rather than writing back a value read earlier, so concurrent transactions can't lose updates
+        let currency_data: CurrencyData = sqlx::query_as(format!("UPDATE currencies SET {0} = {0} + $1 WHERE guild_id = $2 AND currency_code = $3 AND archived_at IS NULL RETURNING *", match kind {
+                TransactionKind::Reserve => "reserves",
+                TransactionKind::Circulation => "circulation"
+            }).as_str())
+            .bind(amount)
+            .bind(self.guild_id)
             .bind(currency_code.clone())
-            .fetch_one(&self.pool).await {
-                Ok(row) => row,
-                Err(e) => return Err(e)
-            };
-        
+            .fetch_one(&mut tx)
+            .await?;
+
+        let (delta_reserves, delta_circulation) = match kind {
+            TransactionKind::Reserve => (Some(amount), None),
+            TransactionKind::Circulation => (None, Some(amount))
+        };
         let transaction_date = Utc::now();
-        let prev_reserves = currency_data.reserves;
-        let new_reserves = prev_reserves + amount;
 
-        let transaction_id: i64 = match sqlx::query("INSERT INTO transactions(transaction_date, currency_id, delta_reserves, initiator) VALUES ($1, $2, $3, $4) RETURNING transaction_id")
+        let transaction_id: i64 = sqlx::query("INSERT INTO transactions(guild_id, transaction_date, currency_id, delta_reserves, delta_circulation, initiator, initiator_id) VALUES ($1, $2, $3, $4, $5, $6, $7) RETURNING transaction_id")
+            .bind(self.guild_id)
             .bind(transaction_date)
             .bind(currency_data.currency_id)
-            .bind(amount)
-            .bind(initiator)
-            .fetch_one(&self.pool)
-            .await {
-                Ok(row) => {
-                    match row.try_get("transaction_id") {
-                        Ok(id) => id,
-                        Err(e) => return Err(e)
-                    }
-                },
-                Err(e) => return Err(e)
-            };
+            .bind(delta_reserves)
+            .bind(delta_circulation)
+            .bind(initiator.name.clone())
+            .bind(initiator.id.0 as i64)
+            .fetch_one(&mut tx)
+            .await?
+            .try_get("transaction_id")?;
 
-        match sqlx::query("UPDATE currencies SET reserves = $1 WHERE currency_id = $2")
-            .bind(new_reserves)
-            .bind(currency_data.currency_id)
-            .execute(&self.pool)
-            .await {
-                Ok(_) => {},
-                Err(e) => return Err(e)
-            };
+        Self::snapshot_currency(&mut tx, &currency_data, transaction_date).await?;
+        tx.commit().await?;
 
-        Ok(TransactionData {
+        Ok((TransactionData {
             transaction_id,
             transaction_date,
+            currency_id: currency_data.currency_id,
             currency_code,
-            delta_reserves: Some(amount),
-            delta_circulation: None
-        })
+            delta_reserves,
+            delta_circulation,
+            initiator: initiator.name.clone(),
+            initiator_id: Some(initiator.id.0 as i64),
+            reverts_transaction_id: None,
+            reverted_by: None
+        }, currency_data))
     }
 
-    pub async fn circulation_modify(&self, currency_code: String, amount: i64, initiator: String) -> Result<TransactionData, sqlx::Error> {
-        let currency_data: CurrencyData = match sqlx::query_as("SELECT * FROM currencies WHERE currency_code = $1")
-            .bind(currency_code.clone())
-            .fetch_one(&self.pool).await {
-                Ok(row) => row,
-                Err(e) => return Err(e)
-            };
-        
-        let transaction_date = Utc::now();
-        let prev_circulation = currency_data.circulation;
-        let new_circulation = prev_circulation + amount;
+    pub async fn revert_transaction(&self, transaction_id: i64, initiator: &User) -> Result<(TransactionData, CurrencyData), RevertError> {
+        let mut tx = self.pool.begin().await?;
+
+        let original = sqlx::query("SELECT currency_id, delta_reserves, delta_circulation, reverts_transaction_id FROM transactions WHERE guild_id = $1 AND transaction_id = $2 FOR UPDATE")
+            .bind(self.guild_id)
+            .bind(transaction_id)
+            .fetch_one(&mut tx)
+            .await?;
+
+        if let Some(reverted_id) = original.try_get::<Option<i64>, _>("reverts_transaction_id")? {
+            return Err(RevertError::IsReversal(reverted_id))
+        }
+
+        if let Some(row) = sqlx::query("SELECT transaction_id FROM transactions WHERE reverts_transaction_id = $1")
+            .bind(transaction_id)
+            .fetch_optional(&mut tx)
+            .await? {
+                let reversal_id: i64 = row.try_get("transaction_id")?;
+                return Err(RevertError::AlreadyReverted(reversal_id))
+            }
+
+        let currency_id: i64 = original.try_get("currency_id")?;
+        let delta_reserves = original.try_get::<Option<i64>, _>("delta_reserves")?.map(|amount| -amount);
+        let delta_circulation = original.try_get::<Option<i64>, _>("delta_circulation")?.map(|amount| -amount);
+
+        let currency_data: CurrencyData = sqlx::query_as("UPDATE currencies SET reserves = reserves + COALESCE($1, 0), circulation = circulation + COALESCE($2, 0) WHERE currency_id = $3 AND archived_at IS NULL RETURNING *")
+            .bind(delta_reserves)
+            .bind(delta_circulation)
+            .bind(currency_id)
+            .fetch_one(&mut tx)
+            .await?;
 
-        let transaction_id: i64 = match sqlx::query("INSERT INTO transactions(transaction_date, currency_id, delta_circulation, initiator) VALUES ($1, $2, $3, $4) RETURNING transaction_id")
+        let transaction_date = Utc::now();
+        let reversal_id: i64 = sqlx::query("INSERT INTO transactions(guild_id, transaction_date, currency_id, delta_reserves, delta_circulation, initiator, initiator_id, reverts_transaction_id) VALUES ($1, $2, $3, $4, $5, $6, $7, $8) RETURNING transaction_id")
+            .bind(self.guild_id)
             .bind(transaction_date)
-            .bind(currency_data.currency_id)
-            .bind(amount)
-            .bind(initiator)
-            .fetch_one(&self.pool)
-            .await {
-                Ok(row) => {
-                    match row.try_get("transaction_id") {
-                        Ok(id) => id,
-                        Err(e) => return Err(e)
-                    }
-                },
-                Err(e) => return Err(e)
-            };
+            .bind(currency_id)
+            .bind(delta_reserves)
+            .bind(delta_circulation)
+            .bind(initiator.name.clone())
+            .bind(initiator.id.0 as i64)
+            .bind(transaction_id)
+            .fetch_one(&mut tx)
+            .await?
+            .try_get("transaction_id")?;
 
-        match sqlx::query("UPDATE currencies SET circulation = $1 WHERE currency_id = $2")
-            .bind(new_circulation)
-            .bind(currency_data.currency_id)
-            .execute(&self.pool)
-            .await {
-                Ok(_) => {},
-                Err(e) => return Err(e)
-            };
+        Self::snapshot_currency(&mut tx, &currency_data, transaction_date).await?;
+        tx.commit().await?;
 
-        Ok(TransactionData {
-            transaction_id,
+        Ok((TransactionData {
+            transaction_id: reversal_id,
             transaction_date,
-            currency_code,
-            delta_reserves: None,
-            delta_circulation: Some(amount)
-        })
+            currency_id,
+            currency_code: currency_data.currency_code.clone(),
+            delta_reserves,
+            delta_circulation,
+            initiator: initiator.name.clone(),
+            initiator_id: Some(initiator.id.0 as i64),
+            reverts_transaction_id: Some(transaction_id),
+            reverted_by: None
+        }, currency_data))
     }
 
-    pub async fn modify_currency_meta(&self, currency_code: String, kind: ModifyMetaType, data: String) -> Result<CurrencyData, sqlx::Error> {
-        let sql_result = sqlx::query_as(format!("UPDATE currencies SET {} = $1 WHERE currency_code = $2 RETURNING *", match kind {
-                ModifyMetaType::Name => "currency_name",
-                ModifyMetaType::Code => "currency_code",
-                ModifyMetaType::State => "state"
-            }).as_str())
-            .bind(data)
+    async fn snapshot_currency(tx: &mut Transaction<'_, Postgres>, currency_data: &CurrencyData, snapshot_date: DateTime<Utc>) -> Result<(), sqlx::Error> {
+        sqlx::query("INSERT INTO snapshots(currency_id, snapshot_date, value, circulation, reserves) VALUES ($1, $2, $3, $4, $5)")
+            .bind(currency_data.currency_id)
+            .bind(snapshot_date)
+            .bind(currency_data.value)
+            .bind(currency_data.circulation)
+            .bind(currency_data.reserves)
+            .execute(tx).await?;
+        Ok(())
+    }
+
+    // Snapshots every active currency at once, for the worker's interval snapshots
+    pub async fn take_snapshots(&self) -> Result<u64, sqlx::Error> {
+        let result = sqlx::query("INSERT INTO snapshots(currency_id, snapshot_date, value, circulation, reserves) SELECT currency_id, $1, value, circulation, reserves FROM currencies WHERE guild_id = $2 AND archived_at IS NULL")
+            .bind(Utc::now())
+            .bind(self.guild_id)
+            .execute(&self.pool).await?;
+        Ok(result.rows_affected())
+    }
+
+    pub async fn modify_currency_meta(&self, currency_code: String, kind: ModifyMetaType, data: String, actor: &User) -> Result<CurrencyData, sqlx::Error> {
+        let (column, action) = match kind {
+            ModifyMetaType::Name => ("currency_name", "modify-name"),
+            ModifyMetaType::Code => ("currency_code", "modify-code"),
+            ModifyMetaType::State => ("state", "modify-state")
+        };
+        let mut tx = self.pool.begin().await?;
+
+        let before: CurrencyData = sqlx::query_as("SELECT * FROM currencies WHERE guild_id = $1 AND currency_code = $2 AND archived_at IS NULL FOR UPDATE")
+            .bind(self.guild_id)
             .bind(currency_code)
-            .fetch_one(&self.pool).await?;
+            .fetch_one(&mut tx).await?;
+
+        let after: CurrencyData = sqlx::query_as(format!("UPDATE currencies SET {column} = $1 WHERE currency_id = $2 RETURNING *").as_str())
+            .bind(data)
+            .bind(before.currency_id)
+            .fetch_one(&mut tx).await?;
 
-        Ok(sql_result)
+        self.audit(&mut tx, actor, action, Some(after.currency_id), to_json(&before), to_json(&after)).await?;
+        tx.commit().await?;
+        Ok(after)
     }
 
-    pub async fn danger_recreate_database(&self) -> Result<(), sqlx::Error> {
-        match sqlx::query("DROP TABLE IF EXISTS transactions;")
-            .execute(&self.pool)
-            .await {
-                Ok(_) => {},
-                Err(e) => return Err(e)
-            };
-        match sqlx::query("DROP TABLE IF EXISTS records;")
-            .execute(&self.pool)
-            .await {
-                Ok(_) => {},
-                Err(e) => return Err(e)
-            }
-        match sqlx::query("DROP TABLE IF EXISTS currencies;")
-            .execute(&self.pool)
-            .await {
-                Ok(_) => {},
-                Err(e) => return Err(e)
-            };
-        match sqlx::query("CREATE TABLE IF NOT EXISTS currencies(
-        currency_id BIGSERIAL NOT NULL,
-        currency_code TEXT NOT NULL UNIQUE,
-        currency_name TEXT NOT NULL,
-        state TEXT NOT NULL,
-        circulation BIGINT NOT NULL,
-        reserves BIGINT NOT NULL,
-        owner TEXT NOT NULL,
-        value DOUBLE PRECISION GENERATED ALWAYS AS (
-            CASE WHEN reserves <= 0 THEN 0
-                 WHEN circulation <= 0 THEN 0 
-                 ELSE (
-                     CAST(reserves AS DOUBLE PRECISION) / CAST(circulation AS DOUBLE PRECISION)
-                 ) 
-                 END
-            ) STORED,
-        PRIMARY KEY (currency_id)
-    );")
-            .execute(&self.pool)
-            .await {
-                Ok(_) => {},
-                Err(e) => return Err(e)
-            };
-        match sqlx::query("CREATE TABLE transactions(
-            transaction_id BIGSERIAL NOT NULL,
-            transaction_date DATE NOT NULL,
-            currency_id BIGINT NOT NULL,
-            delta_circulation BIGINT,
-            delta_reserves BIGINT,
-            initiator TEXT NOT NULL,
-            PRIMARY KEY (transaction_id),
-            FOREIGN KEY (currency_id) REFERENCES currencies(currency_id) ON DELETE CASCADE
-        );")
-            .execute(&self.pool)
-            .await {
-                Ok(_) => {},
-                Err(e) => return Err(e)
-            };
+    // Wipes this server's economy. Managers, transactions, records and snapshots go with their currencies, market hours and the audit log are kept
+    pub async fn danger_recreate_database(&self, actor: &User) -> Result<(), sqlx::Error> {
+        let mut tx = self.pool.begin().await?;
+
+        let before = self.count_rows(&mut tx).await?;
+        for table in ["market_sessions", "currencies"] {
+            sqlx::query(format!("DELETE FROM {table} WHERE guild_id = $1").as_str())
+                .bind(self.guild_id)
+                .execute(&mut tx).await?;
+        }
+
+        self.audit(&mut tx, actor, "recreate-database", None, Some(before), None).await?;
+        tx.commit().await
+    }
 
-        match sqlx::query("CREATE TABLE IF NOT EXISTS records(
-        record_id BIGSERIAL NOT NULL,
-        record_date DATE NOT NULL,
-        currency_id BIGINT NOT NULL,
-        opening_value DOUBLE PRECISION,
-        closing_value DOUBLE PRECISION,
-        delta_value DOUBLE PRECISION GENERATED ALWAYS AS (closing_value - opening_value) STORED,
-        growth SMALLINT GENERATED ALWAYS AS (
-            CASE WHEN (closing_value - opening_value) = 0 THEN 0
-                 WHEN (closing_value - opening_value) > 0 THEN 1
-                 ELSE -1
-                 END
-            ) STORED,
-        PRIMARY KEY (record_id),
-        FOREIGN KEY (currency_id) REFERENCES currencies(currency_id) ON DELETE CASCADE
-        )
-    ").execute(&self.pool).await {
-        Ok(_) => {},
-        Err(e) => return Err(e)
-    };
+    // For events that don't change anything, like permission denials. Changes are audited by the method making them
+    pub async fn log_audit_event(&self, actor: &User, action: &str, currency_id: Option<i64>, details: String) -> Result<(), sqlx::Error> {
+        sqlx::query("INSERT INTO audit_log(guild_id, audit_date, actor_id, actor_name, action, currency_id, details) VALUES ($1, $2, $3, $4, $5, $6, $7)")
+            .bind(self.guild_id)
+            .bind(Utc::now())
+            .bind(actor.id.0 as i64)
+            .bind(actor.name.clone())
+            .bind(action)
+            .bind(currency_id)
+            .bind(details)
+            .execute(&self.pool).await?;
+        Ok(())
+    }
+
+    // Written in the same transaction as the change, so a change is never made without its entry
+    async fn audit(&self, tx: &mut Transaction<'_, Postgres>, actor: &User, action: &str, currency_id: Option<i64>, before: Option<Value>, after: Option<Value>) -> Result<(), sqlx::Error> {
+        sqlx::query("INSERT INTO audit_log(guild_id, audit_date, actor_id, actor_name, action, currency_id, before_data, after_data) VALUES ($1, $2, $3, $4, $5, $6, $7, $8)")
+            .bind(self.guild_id)
+            .bind(Utc::now())
+            .bind(actor.id.0 as i64)
+            .bind(actor.name.clone())
+            .bind(action)
+            .bind(currency_id)
+            .bind(before)
+            .bind(after)
+            .execute(tx).await?;
         Ok(())
     }
 
-    pub async fn insert_record(&self, currency_id: i64, opening_value: f64, closing_value: f64) -> Result<RecordData, sqlx::Error> {
-        let todays_date: chrono::NaiveDate = Utc::now().date_naive();
-        sqlx::query_as("INSERT INTO records(record_date, currency_id, opening_value, closing_value) VALUES ($1, $2, $3, $4) RETURNING *")
-            .bind(todays_date)
+    async fn count_rows(&self, tx: &mut Transaction<'_, Postgres>) -> Result<Value, sqlx::Error> {
+        let (currencies, transactions, records): (i64, i64, i64) = sqlx::query_as("SELECT
+                (SELECT COUNT(*) FROM currencies WHERE guild_id = $1),
+                (SELECT COUNT(*) FROM transactions WHERE guild_id = $1),
+                (SELECT COUNT(*) FROM records WHERE guild_id = $1)")
+            .bind(self.guild_id)
+            .fetch_one(tx).await?;
+        Ok(json!({ "currencies": currencies, "transactions": transactions, "records": records }))
+    }
+
+    pub async fn set_manager(&self, manager: &ManagerData, actor: &User) -> Result<ManagerData, sqlx::Error> {
+        let mut tx = self.pool.begin().await?;
+
+        let before: Option<ManagerData> = sqlx::query_as("SELECT * FROM currency_managers WHERE currency_id = $1 AND user_id = $2 FOR UPDATE")
+            .bind(manager.currency_id)
+            .bind(manager.user_id)
+            .fetch_optional(&mut tx).await?;
+
+        let after: ManagerData = sqlx::query_as("INSERT INTO currency_managers(currency_id, user_id, can_reserve, can_circulation, can_metadata, can_delete)
+            SELECT $1, $2, $3, $4, $5, $6 FROM currencies WHERE currency_id = $1 AND guild_id = $7
+            ON CONFLICT (currency_id, user_id) DO UPDATE SET can_reserve = $3, can_circulation = $4, can_metadata = $5, can_delete = $6 RETURNING *")
+            .bind(manager.currency_id)
+            .bind(manager.user_id)
+            .bind(manager.can_reserve)
+            .bind(manager.can_circulation)
+            .bind(manager.can_metadata)
+            .bind(manager.can_delete)
+            .bind(self.guild_id)
+            .fetch_one(&mut tx).await?;
+
+        self.audit(&mut tx, actor, "set-manager", Some(manager.currency_id), before.as_ref().and_then(to_json), to_json(&after)).await?;
+        tx.commit().await?;
+        Ok(after)
+    }
+
+    pub async fn remove_manager(&self, currency_id: i64, user_id: i64, actor: &User) -> Result<bool, sqlx::Error> {
+        let mut tx = self.pool.begin().await?;
+
+        let removed: Option<ManagerData> = sqlx::query_as("DELETE FROM currency_managers m USING currencies c WHERE c.currency_id = m.currency_id AND c.guild_id = $1 AND m.currency_id = $2 AND m.user_id = $3 RETURNING m.*")
+            .bind(self.guild_id)
+            .bind(currency_id)
+            .bind(user_id)
+            .fetch_optional(&mut tx).await?;
+
+        let Some(removed) = removed else {
+            return Ok(false)
+        };
+        self.audit(&mut tx, actor, "remove-manager", Some(currency_id), to_json(&removed), None).await?;
+        tx.commit().await?;
+        Ok(true)
+    }
+
+    pub async fn transfer_ownership(&self, currency_id: i64, owner: String, owner_id: i64, actor: &User) -> Result<CurrencyData, sqlx::Error> {
+        let mut tx = self.pool.begin().await?;
+
+        let before: CurrencyData = sqlx::query_as("SELECT * FROM currencies WHERE guild_id = $1 AND currency_id = $2 FOR UPDATE")
+            .bind(self.guild_id)
+            .bind(currency_id)
+            .fetch_one(&mut tx).await?;
+
+        let currency_data: CurrencyData = sqlx::query_as("UPDATE currencies SET owner = $1, owner_id = $2 WHERE currency_id = $3 RETURNING *")
+            .bind(owner)
+            .bind(owner_id)
             .bind(currency_id)
-            .bind(opening_value)
-            .bind(closing_value)
-            .fetch_one(&self.pool).await
+            .fetch_one(&mut tx).await?;
+
+        // The new owner has every permission anyway, so a separate manager entry would only be confusing
+        sqlx::query("DELETE FROM currency_managers WHERE currency_id = $1 AND user_id = $2")
+            .bind(currency_id)
+            .bind(owner_id)
+            .execute(&mut tx).await?;
+
+        self.audit(&mut tx, actor, "transfer-ownership", Some(currency_id), to_json(&before), to_json(&currency_data)).await?;
+        tx.commit().await?;
+        Ok(currency_data)
+    }
+
+    pub async fn set_admin_role(&self, admin_role_id: Option<i64>, actor: &User) -> Result<GuildSettings, sqlx::Error> {
+        let mut tx = self.pool.begin().await?;
+
+        let before: Option<GuildSettings> = sqlx::query_as("SELECT * FROM guild_settings WHERE guild_id = $1 FOR UPDATE")
+            .bind(self.guild_id)
+            .fetch_optional(&mut tx).await?;
+
+        let after: GuildSettings = sqlx::query_as("INSERT INTO guild_settings(guild_id, admin_role_id) VALUES ($1, $2)
+            ON CONFLICT (guild_id) DO UPDATE SET admin_role_id = $2 RETURNING *")
+            .bind(self.guild_id)
+            .bind(admin_role_id)
+            .fetch_one(&mut tx).await?;
+
+        self.audit(&mut tx, actor, "set-admin-role", None, before.as_ref().and_then(to_json), to_json(&after)).await?;
+        tx.commit().await?;
+        Ok(after)
+    }
+
+    pub async fn set_ledger_channel(&self, ledger_channel_id: Option<i64>, actor: &User) -> Result<GuildSettings, sqlx::Error> {
+        let mut tx = self.pool.begin().await?;
+
+        let before: Option<GuildSettings> = sqlx::query_as("SELECT * FROM guild_settings WHERE guild_id = $1 FOR UPDATE")
+            .bind(self.guild_id)
+            .fetch_optional(&mut tx).await?;
+
+        let after: GuildSettings = sqlx::query_as("INSERT INTO guild_settings(guild_id, ledger_channel_id) VALUES ($1, $2)
+            ON CONFLICT (guild_id) DO UPDATE SET ledger_channel_id = $2 RETURNING *")
+            .bind(self.guild_id)
+            .bind(ledger_channel_id)
+            .fetch_one(&mut tx).await?;
+
+        self.audit(&mut tx, actor, "set-ledger-channel", None, before.as_ref().and_then(to_json), to_json(&after)).await?;
+        tx.commit().await?;
+        Ok(after)
+    }
+
+    pub async fn set_report_channel(&self, report_channel_id: Option<i64>, actor: &User) -> Result<GuildSettings, sqlx::Error> {
+        let mut tx = self.pool.begin().await?;
+
+        let before: Option<GuildSettings> = sqlx::query_as("SELECT * FROM guild_settings WHERE guild_id = $1 FOR UPDATE")
+            .bind(self.guild_id)
+            .fetch_optional(&mut tx).await?;
+
+        let after: GuildSettings = sqlx::query_as("INSERT INTO guild_settings(guild_id, report_channel_id) VALUES ($1, $2)
+            ON CONFLICT (guild_id) DO UPDATE SET report_channel_id = $2 RETURNING *")
+            .bind(self.guild_id)
+            .bind(report_channel_id)
+            .fetch_one(&mut tx).await?;
+
+        self.audit(&mut tx, actor, "set-report-channel", None, before.as_ref().and_then(to_json), to_json(&after)).await?;
+        tx.commit().await?;
+        Ok(after)
+    }
+
+    pub async fn set_market_config(&self, config: &MarketConfig, actor: &User) -> Result<MarketConfig, sqlx::Error> {
+        let mut tx = self.pool.begin().await?;
+
+        let before: Option<MarketConfig> = sqlx::query_as("SELECT * FROM market_config WHERE guild_id = $1 FOR UPDATE")
+            .bind(self.guild_id)
+            .fetch_optional(&mut tx).await?;
+
+        let after: MarketConfig = sqlx::query_as("INSERT INTO market_config(guild_id, open_time, close_time, timezone, trading_days, snapshot_interval_minutes) VALUES ($1, $2, $3, $4, $5, $6)
+            ON CONFLICT (guild_id) DO UPDATE SET open_time = $2, close_time = $3, timezone = $4, trading_days = $5, snapshot_interval_minutes = $6 RETURNING *")
+            .bind(self.guild_id)
+            .bind(config.open_time)
+            .bind(config.close_time)
+            .bind(config.timezone.clone())
+            .bind(config.trading_days)
+            .bind(config.snapshot_interval_minutes)
+            .fetch_one(&mut tx).await?;
+
+        self.audit(&mut tx, actor, "set-market-hours", None, before.as_ref().and_then(to_json), to_json(&after)).await?;
+        tx.commit().await?;
+        Ok(after)
+    }
+
+    // Stores the opening value of every active currency, so the session survives a restart
+    pub async fn open_session(&self, record_date: NaiveDate, closes_at: DateTime<Utc>) -> Result<SessionData, sqlx::Error> {
+        let mut tx = self.pool.begin().await?;
+
+        let session: SessionData = sqlx::query_as("INSERT INTO market_sessions(guild_id, record_date, opened_at, closes_at) VALUES ($1, $2, $3, $4) RETURNING *")
+            .bind(self.guild_id)
+            .bind(record_date)
+            .bind(Utc::now())
+            .bind(closes_at)
+            .fetch_one(&mut tx).await?;
+
+        sqlx::query("INSERT INTO opening_snapshots(session_id, currency_id, opening_value) SELECT $1, currency_id, value FROM currencies WHERE guild_id = $2 AND archived_at IS NULL")
+            .bind(session.session_id)
+            .bind(self.guild_id)
+            .execute(&mut tx).await?;
+
+        // Also kept as a snapshot, so the day can be rebuilt from snapshots alone
+        sqlx::query("INSERT INTO snapshots(currency_id, snapshot_date, value, circulation, reserves) SELECT currency_id, $1, value, circulation, reserves FROM currencies WHERE guild_id = $2 AND archived_at IS NULL")
+            .bind(session.opened_at)
+            .bind(self.guild_id)
+            .execute(&mut tx).await?;
+
+        tx.commit().await?;
+        Ok(session)
+    }
+
+    pub async fn reschedule_session(&self, session_id: i64, closes_at: DateTime<Utc>) -> Result<(), sqlx::Error> {
+        sqlx::query("UPDATE market_sessions SET closes_at = $1 WHERE guild_id = $2 AND session_id = $3")
+            .bind(closes_at)
+            .bind(self.guild_id)
+            .bind(session_id)
+            .execute(&self.pool).await?;
+        Ok(())
+    }
+
+    // Writes the session's records for every currency that was open at opening and is still active, in a single statement. Opening snapshots are only needed until then, and snapshots older than the last `SNAPSHOT_RETENTION_SESSIONS` sessions are pruned
+    pub async fn close_session(&self, session: &SessionData, interpolated: bool) -> Result<u64, sqlx::Error> {
+        let mut tx = self.pool.begin().await?;
+
+        // The day's high and low also cover the opening and closing values, which aren't always snapshotted
+        let result = sqlx::query("INSERT INTO records(guild_id, record_date, currency_id, opening_value, closing_value, closing_circulation, closing_reserves, interpolated, high_value, low_value)
+            SELECT c.guild_id, $1, c.currency_id, o.opening_value, c.value, c.circulation, c.reserves, $2,
+                GREATEST(o.opening_value, c.value, MAX(s.value)), LEAST(o.opening_value, c.value, MIN(s.value))
+            FROM opening_snapshots o
+            JOIN currencies c ON c.currency_id = o.currency_id
+            LEFT JOIN snapshots s ON s.currency_id = c.currency_id AND s.snapshot_date >= $3
+            WHERE o.session_id = $4 AND c.guild_id = $5 AND c.archived_at IS NULL
+            GROUP BY c.currency_id, o.opening_value
+            ON CONFLICT (currency_id, record_date) DO UPDATE SET
+                opening_value = EXCLUDED.opening_value, closing_value = EXCLUDED.closing_value,
+                closing_circulation = EXCLUDED.closing_circulation, closing_reserves = EXCLUDED.closing_reserves,
+                interpolated = EXCLUDED.interpolated, high_value = EXCLUDED.high_value, low_value = EXCLUDED.low_value")
+            .bind(session.record_date)
+            .bind(interpolated)
+            .bind(session.opened_at)
+            .bind(session.session_id)
+            .bind(self.guild_id)
+            .execute(&mut tx).await?;
+
+        sqlx::query("UPDATE market_sessions SET closed_at = $1 WHERE session_id = $2")
+            .bind(Utc::now())
+            .bind(session.session_id)
+            .execute(&mut tx).await?;
+        sqlx::query("DELETE FROM opening_snapshots WHERE session_id = $1")
+            .bind(session.session_id)
+            .execute(&mut tx).await?;
+
+        // Nothing is pruned until the server has closed more sessions than are kept
+        sqlx::query("DELETE FROM snapshots s USING currencies c
+            WHERE c.currency_id = s.currency_id AND c.guild_id = $1 AND s.snapshot_date < (
+                SELECT opened_at FROM market_sessions WHERE guild_id = $1 AND closed_at IS NOT NULL
+                ORDER BY opened_at DESC OFFSET $2 LIMIT 1
+            )")
+            .bind(self.guild_id)
+            .bind(SNAPSHOT_RETENTION_SESSIONS - 1)
+            .execute(&mut tx).await?;
+
+        tx.commit().await?;
+        Ok(result.rows_affected())
+    }
+
+    // Replaces a day's records with ones derived from the snapshots taken between `from` and `to`
+    pub async fn rebuild_records(&self, record_date: NaiveDate, from: DateTime<Utc>, to: DateTime<Utc>) -> Result<u64, sqlx::Error> {
+        let result = sqlx::query("INSERT INTO records(guild_id, record_date, currency_id, opening_value, closing_value, closing_circulation, closing_reserves, high_value, low_value)
+            SELECT c.guild_id, $1, c.currency_id,
+                (ARRAY_AGG(s.value ORDER BY s.snapshot_date))[1],
+                (ARRAY_AGG(s.value ORDER BY s.snapshot_date DESC))[1],
+                (ARRAY_AGG(s.circulation ORDER BY s.snapshot_date DESC))[1],
+                (ARRAY_AGG(s.reserves ORDER BY s.snapshot_date DESC))[1],
+                MAX(s.value), MIN(s.value)
+            FROM snapshots s JOIN currencies c ON c.currency_id = s.currency_id
+            WHERE c.guild_id = $4 AND c.archived_at IS NULL AND s.snapshot_date BETWEEN $2 AND $3
+            GROUP BY c.currency_id
+            ON CONFLICT (currency_id, record_date) DO UPDATE SET
+                opening_value = EXCLUDED.opening_value, closing_value = EXCLUDED.closing_value,
+                closing_circulation = EXCLUDED.closing_circulation, closing_reserves = EXCLUDED.closing_reserves,
+                interpolated = FALSE, high_value = EXCLUDED.high_value, low_value = EXCLUDED.low_value")
+            .bind(record_date)
+            .bind(from)
+            .bind(to)
+            .bind(self.guild_id)
+            .execute(&self.pool).await?;
+
+        Ok(result.rows_affected())
+    }
+
+    pub async fn insert_interpolated_records(&self, records: &[RecordData]) -> Result<(), sqlx::Error> {
+        for chunk in records.chunks(INSERT_BATCH_SIZE) {
+            // Never replaces a record that was actually observed
+            QueryBuilder::<Postgres>::new("INSERT INTO records(guild_id, record_date, currency_id, opening_value, closing_value, interpolated) ")
+                .push_values(chunk, |mut row, record| {
+                    row.push_bind(self.guild_id)
+                        .push_bind(record.record_date)
+                        .push_bind(record.currency_id)
+                        .push_bind(record.opening_value)
+                        .push_bind(record.closing_value)
+                        .push_bind(true);
+                })
+                .push(" ON CONFLICT (currency_id, record_date) DO NOTHING")
+                .build().execute(&self.pool).await?;
+        }
+        Ok(())
+    }
+
+    // Replaces this server's currencies with the contents of an export. Other servers can be using the exported IDs, so every row gets a new ID and the links between rows are remapped
+    pub async fn import_database(&self, export: &DatabaseExport, actor: &User) -> Result<(), sqlx::Error> {
+        let mut tx = self.pool.begin().await?;
+
+        let before = self.count_rows(&mut tx).await?;
+        sqlx::query("DELETE FROM currencies WHERE guild_id = $1")
+            .bind(self.guild_id)
+            .execute(&mut tx).await?;
+
+        let mut currency_ids = HashMap::new();
+        for currency in &export.currencies {
+            let currency_id: i64 = sqlx::query("INSERT INTO currencies(guild_id, currency_code, currency_name, state, circulation, reserves, owner, owner_id, archived_at) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9) RETURNING currency_id")
+                .bind(self.guild_id)
+                .bind(currency.currency_code.clone())
+                .bind(currency.currency_name.clone())
+                .bind(currency.state.clone())
+                .bind(currency.circulation)
+                .bind(currency.reserves)
+                .bind(currency.owner.clone())
+                .bind(currency.owner_id)
+                .bind(currency.archived_at)
+                .fetch_one(&mut tx).await?
+                .try_get("currency_id")?;
+            currency_ids.insert(currency.currency_id, currency_id);
+        }
+
+        let managers: Vec<(i64, &ManagerData)> = export.managers.iter()
+            .filter_map(|manager| currency_ids.get(&manager.currency_id).map(|currency_id| (*currency_id, manager)))
+            .collect();
+        for chunk in managers.chunks(INSERT_BATCH_SIZE) {
+            QueryBuilder::<Postgres>::new("INSERT INTO currency_managers(currency_id, user_id, can_reserve, can_circulation, can_metadata, can_delete) ")
+                .push_values(chunk, |mut row, (currency_id, manager)| {
+                    row.push_bind(*currency_id)
+                        .push_bind(manager.user_id)
+                        .push_bind(manager.can_reserve)
+                        .push_bind(manager.can_circulation)
+                        .push_bind(manager.can_metadata)
+                        .push_bind(manager.can_delete);
+                })
+                .build().execute(&mut tx).await?;
+        }
+
+        // Reversals always have a higher ID than the transaction they revert, so in ID order the reverted transaction's new ID is already known
+        let mut transactions: Vec<&TransactionData> = export.transactions.iter().collect();
+        transactions.sort_by_key(|transaction| transaction.transaction_id);
+        let mut transaction_ids = HashMap::new();
+        for transaction in transactions {
+            let Some(currency_id) = currency_ids.get(&transaction.currency_id) else {
+                continue
+            };
+            let transaction_id: i64 = sqlx::query("INSERT INTO transactions(guild_id, transaction_date, currency_id, delta_reserves, delta_circulation, initiator, initiator_id, reverts_transaction_id) VALUES ($1, $2, $3, $4, $5, $6, $7, $8) RETURNING transaction_id")
+                .bind(self.guild_id)
+                .bind(transaction.transaction_date)
+                .bind(currency_id)
+                .bind(transaction.delta_reserves)
+                .bind(transaction.delta_circulation)
+                .bind(transaction.initiator.clone())
+                .bind(transaction.initiator_id)
+                .bind(transaction.reverts_transaction_id.and_then(|reverted_id| transaction_ids.get(&reverted_id).copied()))
+                .fetch_one(&mut tx).await?
+                .try_get("transaction_id")?;
+            transaction_ids.insert(transaction.transaction_id, transaction_id);
+        }
+
+        let records: Vec<(i64, &RecordData)> = export.records.iter()
+            .filter_map(|record| currency_ids.get(&record.currency_id).map(|currency_id| (*currency_id, record)))
+            .collect();
+        for chunk in records.chunks(INSERT_BATCH_SIZE) {
+            QueryBuilder::<Postgres>::new("INSERT INTO records(guild_id, record_date, currency_id, opening_value, closing_value, closing_circulation, closing_reserves, interpolated, high_value, low_value) ")
+                .push_values(chunk, |mut row, (currency_id, record)| {
+                    row.push_bind(self.guild_id)
+                        .push_bind(record.record_date)
+                        .push_bind(*currency_id)
+                        .push_bind(record.opening_value)
+                        .push_bind(record.closing_value)
+                        .push_bind(record.closing_circulation)
+                        .push_bind(record.closing_reserves)
+                        .push_bind(record.interpolated)
+                        .push_bind(record.high_value)
+                        .push_bind(record.low_value);
+                })
+                // Backups taken before records were unique per day can hold duplicates, keep the first of each
+                .push(" ON CONFLICT (currency_id, record_date) DO NOTHING")
+                .build().execute(&mut tx).await?;
+        }
+
+        let after = self.count_rows(&mut tx).await?;
+        self.audit(&mut tx, actor, "import-database", None, Some(before), Some(after)).await?;
+        tx.commit().await
     }
 }
+
+fn to_json<T: Serialize>(data: &T) -> Option<Value> {
+    serde_json::to_value(data).ok()
+}
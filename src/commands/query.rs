@@ -1,19 +1,37 @@
 use crate::types::*;
-use sqlx::postgres::PgPool;
-use futures::TryStreamExt;
+use sqlx::postgres::{PgPool, Postgres};
+use sqlx::QueryBuilder;
+use serenity::model::user::User;
+use chrono::NaiveDate;
+use futures::{Stream, TryStreamExt};
 use tracing::info;
 
+// Only ever sees the economy of one server
 #[derive(Clone)]
 pub struct DBQueryAgent {
-    pool: PgPool
+    pool: PgPool,
+    guild_id: i64
 }
 
 impl DBQueryAgent {
-    pub fn new(pool: PgPool) -> Self {
+    pub fn new(pool: PgPool, guild_id: i64) -> Self {
         DBQueryAgent {
-            pool
+            pool,
+            guild_id
         }
     }
+
+    pub fn guild_id(&self) -> i64 {
+        self.guild_id
+    }
+
+    // Every server with an economy or market hours, so each can have its own workers
+    pub async fn list_guilds(pool: &PgPool) -> Result<Vec<i64>, sqlx::Error> {
+        let guilds: Vec<(i64,)> = sqlx::query_as("SELECT guild_id FROM currencies WHERE guild_id IS NOT NULL UNION SELECT guild_id FROM market_config")
+            .fetch_all(pool)
+            .await?;
+        Ok(guilds.into_iter().map(|(guild_id,)| guild_id).collect())
+    }
 }
 
 #[derive(Copy, Clone)]
@@ -26,10 +44,36 @@ pub enum CurrencySort {
     State
 }
 
+#[derive(Copy, Clone, PartialEq)]
+pub enum TransactionKind {
+    Reserve,
+    Circulation
+}
+
+#[derive(Clone, Default)]
+pub struct TransactionFilter {
+    pub currency_code: String,
+    pub initiator_id: Option<i64>,
+    pub from_date: Option<NaiveDate>,
+    pub to_date: Option<NaiveDate>,
+    pub kind: Option<TransactionKind>
+}
+
+#[derive(Clone, Default)]
+pub struct AuditFilter {
+    pub currency_id: Option<i64>,
+    pub actor_id: Option<i64>
+}
+
+const AUDIT_SELECT: &str = "SELECT a.audit_id, a.audit_date, a.actor_id, a.actor_name, a.action, a.currency_id, c.currency_code, a.details, a.before_data, a.after_data FROM audit_log a LEFT JOIN currencies c ON c.currency_id = a.currency_id";
+
+const TRANSACTION_SELECT: &str = "SELECT t.transaction_id, t.transaction_date, t.currency_id, c.currency_code, t.delta_reserves, t.delta_circulation, t.initiator, t.initiator_id, t.reverts_transaction_id, (SELECT r.transaction_id FROM transactions r WHERE r.reverts_transaction_id = t.transaction_id) AS reverted_by FROM transactions t JOIN currencies c ON c.currency_id = t.currency_id";
+
 impl DBQueryAgent {
     pub async fn get_currency_data(&self, currency_code: String) -> Result<CurrencyData, sqlx::Error> {
         info!("Checking currency code: {currency_code}");
-        match sqlx::query_as("SELECT * FROM currencies WHERE currency_code = $1")
+        match sqlx::query_as("SELECT * FROM currencies WHERE guild_id = $1 AND currency_code = $2 AND archived_at IS NULL")
+            .bind(self.guild_id)
             .bind(currency_code)
             .fetch_one(&self.pool)
             .await {
@@ -37,9 +81,18 @@ impl DBQueryAgent {
                 Err(e) => Err(e)
             }
     }
+
+    pub async fn get_archived_currency_data(&self, currency_code: String) -> Result<CurrencyData, sqlx::Error> {
+        sqlx::query_as("SELECT * FROM currencies WHERE guild_id = $1 AND currency_code = $2 AND archived_at IS NOT NULL")
+            .bind(self.guild_id)
+            .bind(currency_code)
+            .fetch_one(&self.pool)
+            .await
+    }
     
     pub async fn get_transaction_data(&self, transaction_id: i64) -> Result<TransactionData, sqlx::Error> {
-        match sqlx::query_as("SELECT * FROM transactions WHERE transaction_id = $1")
+        match sqlx::query_as(format!("{TRANSACTION_SELECT} WHERE t.guild_id = $1 AND t.transaction_id = $2").as_str())
+            .bind(self.guild_id)
             .bind(transaction_id)
             .fetch_one(&self.pool)
             .await {
@@ -60,9 +113,10 @@ impl DBQueryAgent {
         };
 
         println!("ORDERING BY: {order_by}");
-        let query = format!("SELECT * FROM currencies ORDER BY {}", order_by);
+        let query = format!("SELECT * FROM currencies WHERE guild_id = $1 AND archived_at IS NULL ORDER BY {}", order_by);
 
         let mut stream = sqlx::query_as::<_, CurrencyData>(query.as_str())
+            .bind(self.guild_id)
             .fetch(&self.pool);
 
         let mut currency_vec = vec![];
@@ -81,24 +135,224 @@ impl DBQueryAgent {
         Ok(currency_vec)
     }
 
-    pub async fn get_reports(&self, number: i64, currency_code: String) -> Result<Vec<RecordData>, sqlx::Error> {
+    // Newest first. `number` limits how many records are returned, and `since` excludes records before that date
+    pub async fn get_reports(&self, number: Option<i64>, currency_code: String, since: Option<NaiveDate>) -> Result<Vec<RecordData>, sqlx::Error> {
         let currency_id = match self.get_currency_data(currency_code).await {
             Ok(data) => data.currency_id,
             Err(e) => return Err(e)
         };
 
-        let mut return_vec = vec![];
-        let mut stream = sqlx::query_as("SELECT * FROM records WHERE currency_id = $1 ORDER BY record_id DESC;")
+        let mut query = QueryBuilder::<Postgres>::new("SELECT * FROM records WHERE currency_id = ");
+        query.push_bind(currency_id);
+        if let Some(since) = since {
+            query.push(" AND record_date >= ").push_bind(since);
+        }
+        query.push(" ORDER BY record_date DESC");
+        if let Some(number) = number {
+            query.push(" LIMIT ").push_bind(number);
+        }
+
+        query.build_query_as().fetch_all(&self.pool).await
+    }
+
+    // Every currency's record for one day, for the daily summary
+    pub async fn get_records_on(&self, record_date: NaiveDate) -> Result<Vec<RecordData>, sqlx::Error> {
+        sqlx::query_as("SELECT * FROM records WHERE guild_id = $1 AND record_date = $2 ORDER BY currency_id")
+            .bind(self.guild_id)
+            .bind(record_date)
+            .fetch_all(&self.pool)
+            .await
+    }
+
+    pub async fn get_exchange_rate(&self, from_code: String, to_code: String) -> Result<ExchangeRate, sqlx::Error> {
+        let from = self.get_currency_data(from_code).await?;
+        let to = self.get_currency_data(to_code).await?;
+
+        // Both values are in gold ingots per unit, so the cross rate is just their ratio
+        let rate = if to.value > 0.0 { Some(from.value / to.value) } else { None };
+        let inverse_rate = if from.value > 0.0 { Some(to.value / from.value) } else { None };
+
+        Ok(ExchangeRate {
+            from,
+            to,
+            rate,
+            inverse_rate
+        })
+    }
+
+    pub async fn list_transactions(&self, filter: &TransactionFilter, number: i64, offset: i64) -> Result<Vec<TransactionData>, sqlx::Error> {
+        let mut query = QueryBuilder::<Postgres>::new(TRANSACTION_SELECT);
+        self.push_transaction_filter(&mut query, filter);
+        query
+            .push(" ORDER BY t.transaction_id DESC LIMIT ")
+            .push_bind(number)
+            .push(" OFFSET ")
+            .push_bind(offset);
+
+        query.build_query_as::<TransactionData>()
+            .fetch_all(&self.pool)
+            .await
+    }
+
+    pub async fn count_transactions(&self, filter: &TransactionFilter) -> Result<i64, sqlx::Error> {
+        let mut query = QueryBuilder::<Postgres>::new("SELECT COUNT(*) FROM transactions t JOIN currencies c ON c.currency_id = t.currency_id");
+        self.push_transaction_filter(&mut query, filter);
+
+        let (count,): (i64,) = query.build_query_as()
+            .fetch_one(&self.pool)
+            .await?;
+        Ok(count)
+    }
+
+    fn push_transaction_filter(&self, query: &mut QueryBuilder<'_, Postgres>, filter: &TransactionFilter) {
+        query
+            .push(" WHERE t.guild_id = ")
+            .push_bind(self.guild_id)
+            .push(" AND c.currency_code = ")
+            .push_bind(filter.currency_code.clone());
+
+        if let Some(initiator_id) = filter.initiator_id {
+            query.push(" AND t.initiator_id = ").push_bind(initiator_id);
+        }
+        if let Some(from_date) = filter.from_date {
+            query.push(" AND t.transaction_date >= ").push_bind(from_date);
+        }
+        if let Some(to_date) = filter.to_date.and_then(|date| date.succ_opt()) {
+            query.push(" AND t.transaction_date < ").push_bind(to_date);
+        }
+        match filter.kind {
+            Some(TransactionKind::Reserve) => { query.push(" AND t.delta_reserves IS NOT NULL"); },
+            Some(TransactionKind::Circulation) => { query.push(" AND t.delta_circulation IS NOT NULL"); },
+            None => {}
+        }
+    }
+
+    pub async fn list_audit_entries(&self, filter: &AuditFilter, number: i64, offset: i64) -> Result<Vec<AuditEntry>, sqlx::Error> {
+        let mut query = QueryBuilder::<Postgres>::new(AUDIT_SELECT);
+        self.push_audit_filter(&mut query, filter);
+        query
+            .push(" ORDER BY a.audit_id DESC LIMIT ")
+            .push_bind(number)
+            .push(" OFFSET ")
+            .push_bind(offset);
+
+        query.build_query_as::<AuditEntry>()
+            .fetch_all(&self.pool)
+            .await
+    }
+
+    pub async fn count_audit_entries(&self, filter: &AuditFilter) -> Result<i64, sqlx::Error> {
+        let mut query = QueryBuilder::<Postgres>::new("SELECT COUNT(*) FROM audit_log a");
+        self.push_audit_filter(&mut query, filter);
+
+        let (count,): (i64,) = query.build_query_as()
+            .fetch_one(&self.pool)
+            .await?;
+        Ok(count)
+    }
+
+    fn push_audit_filter(&self, query: &mut QueryBuilder<'_, Postgres>, filter: &AuditFilter) {
+        query
+            .push(" WHERE a.guild_id = ")
+            .push_bind(self.guild_id);
+
+        if let Some(currency_id) = filter.currency_id {
+            query.push(" AND a.currency_id = ").push_bind(currency_id);
+        }
+        if let Some(actor_id) = filter.actor_id {
+            query.push(" AND a.actor_id = ").push_bind(actor_id);
+        }
+    }
+
+    pub async fn get_manager(&self, currency_id: i64, user_id: i64) -> Result<Option<ManagerData>, sqlx::Error> {
+        sqlx::query_as("SELECT m.* FROM currency_managers m JOIN currencies c ON c.currency_id = m.currency_id WHERE c.guild_id = $1 AND m.currency_id = $2 AND m.user_id = $3")
+            .bind(self.guild_id)
             .bind(currency_id)
-            .fetch(&self.pool);
+            .bind(user_id)
+            .fetch_optional(&self.pool)
+            .await
+    }
 
-        let mut i = 0;
-        while let Some(row) = stream.try_next().await? {
-            return_vec.push(row);
-            i += 1;
-            if i >= number { break };
+    pub async fn user_has_permission(&self, currency: &CurrencyData, user: &User, permission: CurrencyPermission) -> Result<bool, sqlx::Error> {
+        if currency.is_owned_by(user) {
+            return Ok(true)
         }
 
-        Ok(return_vec)
+        match self.get_manager(currency.currency_id, user.id.0 as i64).await? {
+            Some(manager) => Ok(manager.allows(permission)),
+            None => Ok(false)
+        }
+    }
+
+    pub async fn get_guild_settings(&self) -> Result<GuildSettings, sqlx::Error> {
+        let settings = sqlx::query_as("SELECT * FROM guild_settings WHERE guild_id = $1")
+            .bind(self.guild_id)
+            .fetch_optional(&self.pool)
+            .await?;
+        Ok(settings.unwrap_or_else(|| GuildSettings::new(self.guild_id)))
+    }
+
+    // Guilds that haven't configured their market use the defaults
+    pub async fn get_market_config(&self) -> Result<MarketConfig, sqlx::Error> {
+        let config = sqlx::query_as("SELECT * FROM market_config WHERE guild_id = $1")
+            .bind(self.guild_id)
+            .fetch_optional(&self.pool)
+            .await?;
+        Ok(config.unwrap_or_else(|| MarketConfig::new(self.guild_id)))
+    }
+
+    // The session the worker was trading when it last stopped, if it never closed
+    pub async fn get_open_session(&self) -> Result<Option<SessionData>, sqlx::Error> {
+        sqlx::query_as("SELECT * FROM market_sessions WHERE guild_id = $1 AND closed_at IS NULL ORDER BY opened_at DESC LIMIT 1")
+            .bind(self.guild_id)
+            .fetch_optional(&self.pool)
+            .await
+    }
+
+    pub async fn get_session(&self, record_date: NaiveDate) -> Result<Option<SessionData>, sqlx::Error> {
+        sqlx::query_as("SELECT * FROM market_sessions WHERE guild_id = $1 AND record_date = $2 ORDER BY opened_at DESC LIMIT 1")
+            .bind(self.guild_id)
+            .bind(record_date)
+            .fetch_optional(&self.pool)
+            .await
+    }
+
+    // Every active currency, however many there are, without holding them all in memory
+    pub fn stream_active_currencies(&self) -> impl Stream<Item = Result<CurrencyData, sqlx::Error>> + '_ {
+        sqlx::query_as("SELECT * FROM currencies WHERE guild_id = $1 AND archived_at IS NULL ORDER BY currency_id")
+            .bind(self.guild_id)
+            .fetch(&self.pool)
+    }
+
+    // Most recent record of every active currency, used to find days that were missed while the bot was offline
+    pub async fn get_latest_records(&self) -> Result<Vec<RecordData>, sqlx::Error> {
+        sqlx::query_as("SELECT DISTINCT ON (r.currency_id) r.* FROM records r JOIN currencies c ON c.currency_id = r.currency_id WHERE c.guild_id = $1 AND c.archived_at IS NULL ORDER BY r.currency_id, r.record_date DESC")
+            .bind(self.guild_id)
+            .fetch_all(&self.pool)
+            .await
+    }
+
+    pub async fn export_database(&self) -> Result<DatabaseExport, sqlx::Error> {
+        let currencies = sqlx::query_as("SELECT * FROM currencies WHERE guild_id = $1 ORDER BY currency_id")
+            .bind(self.guild_id)
+            .fetch_all(&self.pool).await?;
+        let managers = sqlx::query_as("SELECT m.* FROM currency_managers m JOIN currencies c ON c.currency_id = m.currency_id WHERE c.guild_id = $1 ORDER BY m.currency_id, m.user_id")
+            .bind(self.guild_id)
+            .fetch_all(&self.pool).await?;
+        let transactions = sqlx::query_as(format!("{TRANSACTION_SELECT} WHERE t.guild_id = $1 ORDER BY t.transaction_id").as_str())
+            .bind(self.guild_id)
+            .fetch_all(&self.pool).await?;
+        let records = sqlx::query_as("SELECT * FROM records WHERE guild_id = $1 ORDER BY record_id")
+            .bind(self.guild_id)
+            .fetch_all(&self.pool).await?;
+
+        Ok(DatabaseExport {
+            schema_version: crate::MIGRATOR.iter().map(|migration| migration.version).max().unwrap_or(0),
+            exported_at: chrono::Utc::now(),
+            currencies,
+            managers,
+            transactions,
+            records
+        })
     }
 }
@@ -0,0 +1,92 @@
+use crate::auth;
+use crate::commands::manage::*;
+use crate::commands::query::*;
+use crate::types::*;
+use crate::utils;
+use crate::CommandResponseObject;
+use async_trait::async_trait;
+use serenity::builder::CreateApplicationCommandOption;
+use serenity::model::prelude::command::CommandOptionType;
+use serenity::model::prelude::interaction::application_command::{
+    ApplicationCommandInteraction, CommandDataOption, CommandDataOptionValue,
+};
+
+pub struct AdminHandler {}
+
+#[async_trait]
+impl ApplicationCommandHandler for AdminHandler {
+    async fn handle_application_command(&mut self, data: &ApplicationCommandInteraction, query_agent: &DBQueryAgent, manager: &DBManager) -> Result<CommandResponseObject, String> {
+        let options = match utils::get_options(data) {
+            Ok(o) => o,
+            Err(e) => return Err(format!("Error while getting options from command data: {e:?}"))
+        };
+
+        // Without any options this just shows the current role, which anyone can do
+        if options.is_empty() {
+            let settings = match query_agent.get_guild_settings().await {
+                Ok(s) => s,
+                Err(e) => return Err(format!("Error getting the admin role: {e:?}"))
+            };
+            return Ok(CommandResponseObject::text(Self::describe_settings(&settings)))
+        }
+
+        auth::require_guild_admin(data)?;
+        let admin_role_id = Self::parse_options(&options)?;
+
+        let settings = match manager.set_admin_role(admin_role_id, &data.user).await {
+            Ok(s) => s,
+            Err(e) => return Err(format!("Error saving the admin role: {e:?}"))
+        };
+
+        Ok(CommandResponseObject::text(format!("{} changed the admin role\n{}", data.user, Self::describe_settings(&settings))))
+    }
+
+    fn get_name(&self) -> &str { "admin" }
+    fn get_description(&self) -> &str { "View or change the role allowed to use dangerous commands like `/currency database`" }
+    fn get_option_kind(&self) -> CommandOptionType { CommandOptionType::SubCommand }
+    fn register(&self) -> Vec<CreateApplicationCommandOption> {
+        vec![
+            CreateApplicationCommandOption::default()
+                .kind(CommandOptionType::Role)
+                .name("role")
+                .description("Role whose members can use dangerous commands")
+                .clone(),
+            CreateApplicationCommandOption::default()
+                .kind(CommandOptionType::Boolean)
+                .name("clear")
+                .description("Remove the admin role, disabling dangerous commands")
+                .clone()
+        ]
+    }
+}
+
+impl AdminHandler {
+    pub fn new() -> Self {
+        AdminHandler {}
+    }
+
+    fn parse_options(options: &[CommandDataOption]) -> Result<Option<i64>, String> {
+        let mut role = None;
+        let mut clear = false;
+        for option in options {
+            match (option.name.as_str(), option.resolved.clone()) {
+                ("role", Some(CommandDataOptionValue::Role(r))) => role = Some(r.id.0 as i64),
+                ("clear", Some(CommandDataOptionValue::Boolean(c))) => clear = c,
+                _ => {}
+            }
+        }
+
+        match (role, clear) {
+            (Some(_), true) => Err("Error: choose either a new role or `clear`, not both".into()),
+            (None, false) => Err("Error: choose a role, or `clear` to remove it".into()),
+            (role, _) => Ok(role)
+        }
+    }
+
+    fn describe_settings(settings: &GuildSettings) -> String {
+        match settings.admin_role_id {
+            Some(role_id) => format!("> Admin role: <@&{role_id}>"),
+            None => "> Admin role: _none, dangerous commands are disabled_".into()
+        }
+    }
+}
@@ -0,0 +1,188 @@
+use crate::auth;
+use crate::commands::manage::*;
+use crate::commands::query::*;
+use crate::types::*;
+use crate::utils;
+use crate::utils::pending::PendingActions;
+use crate::CommandResponseObject;
+use async_trait::async_trait;
+use serenity::model::application::interaction::message_component::MessageComponentInteraction;
+use serenity::model::application::component::ButtonStyle;
+use serenity::builder::{CreateComponents, CreateApplicationCommandOption};
+use serenity::model::prelude::command::CommandOptionType;
+use serenity::model::prelude::interaction::application_command::{
+    ApplicationCommandInteraction, CommandDataOption, CommandDataOptionValue,
+};
+
+const PAGE_SIZE: i64 = 8;
+
+// Longest summary of what an entry changed, so a full page stays inside Discord's message limit
+const CHANGES_WIDTH: usize = 120;
+
+pub struct AuditHandler {
+    pending: PendingActions<(AuditFilter, i64)>
+}
+
+#[async_trait]
+impl ApplicationCommandHandler for AuditHandler {
+    async fn handle_application_command(&mut self, data: &ApplicationCommandInteraction, query_agent: &DBQueryAgent, _manager: &DBManager) -> Result<CommandResponseObject, String> {
+        auth::require_guild_admin(data)?;
+
+        let options = match utils::get_options(data) {
+            Ok(o) => o,
+            Err(e) => return Err(format!("Error while getting options from command data: {e:?}"))
+        };
+
+        let filter = self.parse_options(query_agent, &options).await?;
+        let (components, page) = self.generate_page(query_agent, filter, 0).await?;
+
+        Ok(CommandResponseObject::interactive(components, page, true))
+    }
+
+    fn get_name(&self) -> &str { "audit" }
+    fn get_description(&self) -> &str { "List who created, changed or deleted currencies and settings" }
+    fn get_option_kind(&self) -> CommandOptionType { CommandOptionType::SubCommand }
+    fn register(&self) -> Vec<CreateApplicationCommandOption> {
+        vec![
+            CreateApplicationCommandOption::default()
+                .kind(CommandOptionType::String)
+                .name("code")
+                .description("Only list changes to this currency, including archived ones")
+                .min_length(3)
+                .max_length(3)
+                .clone(),
+            CreateApplicationCommandOption::default()
+                .kind(CommandOptionType::User)
+                .name("user")
+                .description("Only list changes made by this user")
+                .clone()
+        ]
+    }
+}
+
+#[async_trait]
+impl InteractionResponseHandler for AuditHandler {
+    async fn handle_interaction_response(&mut self, data: &MessageComponentInteraction, query_agent: &DBQueryAgent, _manager: &DBManager) -> Result<CommandResponseObject, String> {
+        let (callsign, token) = utils::split_custom_id(data.data.custom_id.as_str());
+        let (filter, page) = match token.and_then(|token| self.pending.take(token)) {
+            Some(p) => p,
+            None => return Err("This audit log has expired. Please run the command again.".into())
+        };
+
+        let page = match callsign {
+            "audit-next" => page + 1,
+            "audit-previous" => (page - 1).max(0),
+            _ => page
+        };
+
+        let (components, page) = self.generate_page(query_agent, filter, page).await?;
+
+        Ok(CommandResponseObject::interactive_with_feedback(components, page, "", true))
+    }
+
+    fn get_pattern(&self) -> Vec<&str> {
+        vec!["audit-next", "audit-previous"]
+    }
+}
+
+impl AuditHandler {
+    pub fn new() -> Self {
+        AuditHandler {
+            pending: PendingActions::default()
+        }
+    }
+
+    async fn parse_options(&self, query_agent: &DBQueryAgent, options: &[CommandDataOption]) -> Result<AuditFilter, String> {
+        let mut filter = AuditFilter::default();
+
+        for option in options {
+            match (option.name.as_str(), option.resolved.clone()) {
+                ("code", Some(CommandDataOptionValue::String(code))) => {
+                    let currency = match query_agent.get_currency_data(code.clone()).await {
+                        Ok(c) => c,
+                        Err(_) => match query_agent.get_archived_currency_data(code.clone()).await {
+                            Ok(c) => c,
+                            Err(_e) => return Err(format!("Error: could not find the currency code `{code}`"))
+                        }
+                    };
+                    filter.currency_id = Some(currency.currency_id);
+                },
+                ("user", Some(CommandDataOptionValue::User(user, _))) => filter.actor_id = Some(user.id.0 as i64),
+                _ => {}
+            }
+        }
+
+        Ok(filter)
+    }
+
+    async fn generate_page(&mut self, query_agent: &DBQueryAgent, filter: AuditFilter, page: i64) -> Result<(CreateComponents, String), String> {
+        let total = match query_agent.count_audit_entries(&filter).await {
+            Ok(t) => t,
+            Err(e) => return Err(format!("Error while counting audit log entries: {e:?}"))
+        };
+
+        let page_count = ((total + PAGE_SIZE - 1) / PAGE_SIZE).max(1);
+        let page = page.min(page_count - 1);
+
+        let entries = match query_agent.list_audit_entries(&filter, PAGE_SIZE, page * PAGE_SIZE).await {
+            Ok(e) => e,
+            Err(e) => return Err(format!("Error while looking up audit log entries: {e:?}"))
+        };
+
+        let mut final_string = format!("```ansi\nAudit log (page {0} of {page_count}, {total} total)", page + 1);
+        if entries.is_empty() {
+            final_string += "\nNothing has been recorded yet";
+        }
+
+        for entry in entries {
+            let currency = entry.currency_code.clone()
+                .map_or(String::new(), |code| format!(" [\u{001b}[36m{code}\u{001b}[0m]"));
+            final_string += format!(
+                "\n#{0:0>5} {1} \u{001b}[1m{2}\u{001b}[0m \u{001b}[1;33m{3}\u{001b}[0m{currency}",
+                entry.audit_id,
+                entry.audit_date.format("%Y-%m-%d %H:%M"),
+                entry.actor_name,
+                entry.action
+            ).as_str();
+
+            let changes = match entry.changes() {
+                changes if !changes.is_empty() => changes.join(", "),
+                _ => entry.details.clone().unwrap_or_default()
+            };
+            if !changes.is_empty() {
+                let mut summary: String = changes.chars().take(CHANGES_WIDTH).collect();
+                if summary.len() < changes.len() {
+                    summary += "…";
+                }
+                final_string += format!("\n       {summary}").as_str();
+            }
+        }
+
+        final_string += "```";
+
+        let first_page = page == 0;
+        let last_page = page >= page_count - 1;
+        let token = self.pending.insert((filter, page));
+
+        let components = CreateComponents::default()
+            .create_action_row(|action_row| {
+                action_row
+                    .create_button(|button| {
+                        button
+                            .label("Previous")
+                            .style(ButtonStyle::Secondary)
+                            .custom_id(utils::custom_id("audit-previous", &token))
+                            .disabled(first_page)
+                    })
+                    .create_button(|button| {
+                        button
+                            .label("Next")
+                            .style(ButtonStyle::Primary)
+                            .custom_id(utils::custom_id("audit-next", &token))
+                            .disabled(last_page)
+                    })
+            }).clone();
+
+        Ok((components, final_string))
+    }
+}
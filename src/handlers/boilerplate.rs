@@ -26,7 +26,7 @@ impl ApplicationCommandHandler for BoilerplateHandler {
 
 #[async_trait]
 impl InteractionResponseHandler for BoilerplateHandler {
-    async fn handle_interaction_response(&self, data: &MessageComponentInteraction, query_agent: &DBQueryAgent, manager: &DBManager) -> Result<CommandResponseObject, String> {
+    async fn handle_interaction_response(&mut self, data: &MessageComponentInteraction, query_agent: &DBQueryAgent, manager: &DBManager) -> Result<CommandResponseObject, String> {
         Ok(CommandResponseObject::text(""))
     }
 
@@ -1,4 +1,6 @@
+use crate::auth;
 use crate::types::*;
+use crate::ledger;
 use crate::CommandResponseObject;
 use async_trait::async_trait;
 use serenity::model::prelude::interaction::application_command::{
@@ -8,22 +10,20 @@ use serenity::model::prelude::interaction::application_command::{
 use serenity::model::application::interaction::message_component::MessageComponentInteraction;
 use serenity::model::application::component::ButtonStyle;
 use serenity::model::prelude::command::CommandOptionType;
-use serenity::model::user::User;
 use serenity::builder::{CreateComponents, CreateApplicationCommandOption};
 use crate::commands::query::*;
 use crate::commands::manage::*;
 use crate::utils;
+use crate::utils::pending::PendingActions;
 use tracing::info;
 
 pub struct CirculationHandler {
-    transaction_code: String,
-    transaction_amount: i64,
-    transaction_initiator: User
+    pending: PendingActions<PendingTransaction>
 }
 
 #[async_trait]
 impl ApplicationCommandHandler for CirculationHandler {
-    async fn handle_application_command(&mut self, data: &ApplicationCommandInteraction, query_agent: &DBQueryAgent, _manager: &DBManager) -> Result<CommandResponseObject, String> {
+    async fn handle_application_command(&mut self, data: &ApplicationCommandInteraction, query_agent: &DBQueryAgent, manager: &DBManager) -> Result<CommandResponseObject, String> {
         info!("Handling command from `{}`", self.get_name());
         let cmd = match data.data.options.get(0) {
             Some(a) => a,
@@ -58,15 +58,14 @@ impl ApplicationCommandHandler for CirculationHandler {
             }
         };
 
-        self.transaction_code = currency_code.clone();
-        self.transaction_amount = amount;
-        self.transaction_initiator = data.user.clone();
-
         info!("Checking currency data");
-        match query_agent.get_currency_data(currency_code.clone()).await {
-            Ok(data) => Ok(self.generate_command_response(data, amount, add)),
-            Err(e) => Err(format!("An error occured while performing a database lookup: {e:?}"))
-        }
+        let currency_data = auth::authorize(query_agent, manager, currency_code.clone(), &data.user, CurrencyPermission::Circulation).await?;
+        let token = self.pending.insert(PendingTransaction {
+            currency_code,
+            amount,
+            initiator: data.user.clone()
+        });
+        Ok(self.generate_command_response(currency_data, amount, add, &token))
     }
     fn get_name(&self) -> &str { "circulation" }
     fn get_description(&self) -> &str { "Manage circulation amounts of a currency" }
@@ -118,23 +117,25 @@ impl ApplicationCommandHandler for CirculationHandler {
 
 #[async_trait]
 impl InteractionResponseHandler for CirculationHandler {
-    async fn handle_interaction_response(&self, data: &MessageComponentInteraction, query_agent: &DBQueryAgent, manager: &DBManager) -> Result<CommandResponseObject, String> {
-        match data.data.custom_id.as_str() {
+    async fn handle_interaction_response(&mut self, data: &MessageComponentInteraction, _query_agent: &DBQueryAgent, manager: &DBManager) -> Result<CommandResponseObject, String> {
+        let (callsign, token) = utils::split_custom_id(data.data.custom_id.as_str());
+        let pending = match token.and_then(|token| self.pending.take(token)) {
+            Some(p) => p,
+            None => return Err("This transaction has expired or has already been completed. Please run the command again.".into())
+        };
+
+        match callsign {
             "circulation-transaction-confirm" => {
-                info!("Transaction details: code: `{}`, amount: `{}`, initiator: `{}`", self.transaction_code.clone(), self.transaction_amount, self.transaction_initiator.name.clone());
-                let transaction_response = match manager.circulation_modify(self.transaction_code.clone(), self.transaction_amount, self.transaction_initiator.name.clone()).await {
+                info!("Transaction details: code: `{}`, amount: `{}`, initiator: `{}`", pending.currency_code.clone(), pending.amount, pending.initiator.name.clone());
+                let (transaction_response, currency_data) = match manager.circulation_modify(pending.currency_code.clone(), pending.amount, &pending.initiator).await {
                     Ok(data) => data,
                     Err(e) => return Err(format!("Error while completing circulation transaction: `{e:?}`"))
                 };
 
-                let currency_data = match query_agent.get_currency_data(self.transaction_code.clone()).await {
-                            Ok(data) => data,
-                            Err(e) => return Ok(CommandResponseObject::interactive_with_feedback(CreateComponents::default(), format!("Error while completing currency balance check: `{e:?}`"), "", true))
-                        };
-
                 let feedback = format!("Successfully completed currency circulation transaction!");
-                let broadcast = format!("{0} made a currency circulation transaction:\n> Currency: **{1}** `{2}`\n> Nation/State: *{6}*\n> Amount: `{3}{2}`\n> New balance: `{4}{2}`\n> Transaction ID: `#{5:0>5}`", data.user, currency_data.currency_name, self.transaction_code, self.transaction_amount, currency_data.circulation, transaction_response.transaction_id, currency_data.state);
-                Ok(CommandResponseObject::interactive_with_feedback(CreateComponents::default(), feedback, broadcast, true))
+                let broadcast = format!("{0} made a currency circulation transaction:\n> Currency: **{1}** `{2}`\n> Nation/State: *{6}*\n> Amount: `{3}{2}`\n> New balance: `{4}{2}`\n> Transaction ID: `#{5:0>5}`", data.user, currency_data.currency_name, pending.currency_code, pending.amount, currency_data.circulation, transaction_response.transaction_id, currency_data.state);
+                Ok(CommandResponseObject::interactive_with_feedback(CreateComponents::default(), feedback, broadcast, true)
+                    .with_ledger(ledger::transaction(&transaction_response, &currency_data)))
             }
             "circulation-transaction-cancel" => {
                 Ok(CommandResponseObject::interactive_with_feedback(CreateComponents::default(), "Cancelled transaction. No records were updated.", "", true))
@@ -151,9 +152,7 @@ impl InteractionResponseHandler for CirculationHandler {
 impl CirculationHandler {
     pub fn new() -> Self {
         CirculationHandler {
-            transaction_code: String::new(),
-            transaction_amount: 0,
-            transaction_initiator: User::default()
+            pending: PendingActions::default()
         }
     }
 
@@ -192,7 +191,7 @@ impl CirculationHandler {
         Ok((amount, currency_code))
     }
 
-    fn generate_command_response(&self, data: CurrencyData, amount: i64, add: bool) -> CommandResponseObject {
+    fn generate_command_response(&self, data: CurrencyData, amount: i64, add: bool, token: &str) -> CommandResponseObject {
         let new_circulation = data.circulation + amount;
         let mut warning = "";
         let mut confirm_style = ButtonStyle::Primary;
@@ -211,13 +210,13 @@ impl CirculationHandler {
                         button
                             .label("Confirm")
                             .style(confirm_style)
-                            .custom_id("circulation-transaction-confirm")
+                            .custom_id(utils::custom_id("circulation-transaction-confirm", token))
                     })
                     .create_button(|button| {
                         button
                             .label("Cancel")
                             .style(cancel_style)
-                            .custom_id("circulation-transaction-cancel")
+                            .custom_id(utils::custom_id("circulation-transaction-cancel", token))
                     })
             }).clone();
 
@@ -0,0 +1,126 @@
+use crate::charts::{self, ChartTheme};
+use crate::commands::manage::*;
+use crate::commands::query::*;
+use crate::types::*;
+use crate::utils;
+use crate::CommandResponseObject;
+use async_trait::async_trait;
+use chrono::NaiveDate;
+use serenity::builder::{CreateApplicationCommandOption, CreateEmbed};
+use serenity::model::prelude::command::CommandOptionType;
+use serenity::model::prelude::interaction::application_command::{
+    ApplicationCommandInteraction, CommandDataOption, CommandDataOptionValue,
+};
+use tokio::task;
+
+const MAX_CURRENCIES: usize = 5;
+
+pub struct CompareHandler {}
+
+struct CompareOptions {
+    codes: Vec<String>,
+    since: Option<NaiveDate>,
+    normalise: bool,
+    theme: ChartTheme
+}
+
+#[async_trait]
+impl ApplicationCommandHandler for CompareHandler {
+    async fn handle_application_command(&mut self, data: &ApplicationCommandInteraction, query_agent: &DBQueryAgent, _manager: &DBManager) -> Result<CommandResponseObject, String> {
+        let options = match utils::get_options(data) {
+            Ok(o) => o,
+            Err(e) => return Err(format!("Error while parsing options: {e:?}"))
+        };
+
+        let compare_options = self.parse_options(&options)?;
+
+        let mut series = vec![];
+        for code in &compare_options.codes {
+            let currency_data = match query_agent.get_currency_data(code.clone()).await {
+                Ok(d) => d,
+                Err(_e) => return Err(format!("Error: could not find the currency code `{code}`"))
+            };
+            let records = match query_agent.get_reports(None, code.clone(), compare_options.since).await {
+                Ok(r) => r,
+                Err(e) => return Err(format!("Error getting records for `{code}`: {e:?}"))
+            };
+            if records.is_empty() {
+                return Err(format!("Error: **{}** `{code}` has no records in this range yet", currency_data.currency_name))
+            }
+            series.push((currency_data, records));
+        }
+
+        let (normalise, theme) = (compare_options.normalise, compare_options.theme);
+        let rendered = task::spawn_blocking(move || charts::render_comparison(&series, normalise, theme)).await;
+        let png = match rendered.map_err(anyhow::Error::from).and_then(|result| result) {
+            Ok(p) => p,
+            Err(e) => return Err(format!("Error rendering comparison: {e}"))
+        };
+
+        let filename = format!("compare-{}.png", compare_options.codes.join("-"));
+        let embed = CreateEmbed::default()
+            .title(format!("Comparing {}", compare_options.codes.join(", ")))
+            .image(format!("attachment://{filename}"))
+            .clone();
+
+        Ok(CommandResponseObject::embed(embed).with_file(filename, png))
+    }
+
+    fn get_name(&self) -> &str { "compare" }
+    fn get_description(&self) -> &str { "Compare the value history of up to five currencies" }
+    fn get_option_kind(&self) -> CommandOptionType { CommandOptionType::SubCommand }
+    fn register(&self) -> Vec<CreateApplicationCommandOption> {
+        let mut options: Vec<CreateApplicationCommandOption> = (1..=MAX_CURRENCIES).map(|n| {
+            CreateApplicationCommandOption::default()
+                .kind(CommandOptionType::String)
+                .name(format!("code{n}"))
+                .description(format!("Three-letter code of currency {n}"))
+                .min_length(3)
+                .max_length(3)
+                .required(n <= 2)
+                .clone()
+        }).collect();
+
+        options.push(utils::range_option());
+        options.push(
+            CreateApplicationCommandOption::default()
+                .kind(CommandOptionType::Boolean)
+                .name("normalise")
+                .description("Rebase every currency to 100 at the first date they all have a record for (default: no)")
+                .clone()
+        );
+        options.push(utils::theme_option());
+        options
+    }
+}
+
+impl CompareHandler {
+    pub fn new() -> Self {
+        CompareHandler {}
+    }
+
+    fn parse_options(&self, options: &[CommandDataOption]) -> Result<CompareOptions, String> {
+        let mut compare_options = CompareOptions {
+            codes: vec![],
+            since: utils::range_start("30d"),
+            normalise: false,
+            theme: ChartTheme::Dark
+        };
+
+        for option in options {
+            match (option.name.as_str(), option.resolved.clone()) {
+                (name, Some(CommandDataOptionValue::String(code))) if name.starts_with("code") && !compare_options.codes.contains(&code) => compare_options.codes.push(code),
+                ("range", Some(CommandDataOptionValue::String(range))) => compare_options.since = utils::range_start(&range),
+                ("normalise", Some(CommandDataOptionValue::Boolean(normalise))) => compare_options.normalise = normalise,
+                ("theme", Some(CommandDataOptionValue::String(theme))) if theme == "light" => compare_options.theme = ChartTheme::Light,
+                _ => {}
+            }
+        }
+
+        if compare_options.codes.len() < 2 {
+            return Err("Error: at least two different currency codes are needed to compare".into())
+        }
+
+        Ok(compare_options)
+    }
+}
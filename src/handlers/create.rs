@@ -1,6 +1,7 @@
 use crate::commands::manage::*;
 use crate::commands::query::*;
 use crate::types::*;
+use crate::ledger::{self, CurrencyEvent};
 use crate::utils;
 use crate::CommandResponseObject;
 use async_trait::async_trait;
@@ -14,7 +15,7 @@ pub struct CreateHandler {}
 
 #[async_trait]
 impl ApplicationCommandHandler for CreateHandler {
-    async fn handle_application_command(&mut self, data: &ApplicationCommandInteraction, _query_agent: &DBQueryAgent, manager: &DBManager) -> Result<CommandResponseObject, String> {
+    async fn handle_application_command(&mut self, data: &ApplicationCommandInteraction, query_agent: &DBQueryAgent, manager: &DBManager) -> Result<CommandResponseObject, String> {
         let options = match utils::get_options(&data) {
             Ok(o) => o,
             Err(e) => return Err(format!("Error while getting options from command data: {e:?}"))
@@ -31,13 +32,17 @@ impl ApplicationCommandHandler for CreateHandler {
             Err(e) => return Err(format!("Error while parsing options: {e:?}"))
         };
 
+        if let Ok(archived) = query_agent.get_archived_currency_data(currency_code.clone()).await {
+            return Err(format!("Error: the currency code `{currency_code}` belongs to the archived currency **{}**. Restore it with `/currency restore` instead", archived.currency_name))
+        }
+
         let currency_data = match manager.add_currency(
             currency_code.clone(),
             currency_name.clone(),
             initial_circulation,
             initial_reserves,
             currency_state.clone(),
-            data.user.name.clone()
+            &data.user
         ).await {
             Ok(d) => d,
             Err(e) => return Err(format!("Error adding currency to database: {e:?}"))
@@ -53,7 +58,7 @@ impl ApplicationCommandHandler for CreateHandler {
                     currency_data.state,
                     data.user
             ),
-        ))
+        ).with_ledger(ledger::currency_event(CurrencyEvent::Created, &currency_data, &data.user)))
     }
 
     fn get_name(&self) -> &str { "create" }
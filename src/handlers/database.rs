@@ -1,7 +1,9 @@
+use crate::auth;
 use crate::commands::manage::*;
 use crate::commands::query::*;
 use crate::types::*;
 use crate::utils;
+use crate::utils::pending::PendingActions;
 use crate::CommandResponseObject;
 use async_trait::async_trait;
 use serenity::model::application::interaction::message_component::MessageComponentInteraction;
@@ -11,26 +13,30 @@ use serenity::model::application::component::ButtonStyle;
 use serenity::builder::{CreateComponents, CreateApplicationCommandOption};
 use serenity::model::application::component::InputTextStyle;
 use serenity::model::prelude::command::CommandOptionType;
-use serenity::model::prelude::interaction::application_command::ApplicationCommandInteraction;
+use serenity::model::prelude::interaction::application_command::{ApplicationCommandInteraction, CommandDataOption, CommandDataOptionValue};
 use serenity::model::application::interaction::modal::ModalSubmitInteraction;
 
+// Typed into the confirmation modal before anything is destroyed
+const RECREATE_CONFIRMATION: &str = "delete everything";
+const IMPORT_CONFIRMATION: &str = "replace everything";
+
 pub struct DatabaseHandler {
-    db_password: String
+    db_password: Option<String>,
+    pending_imports: PendingActions<DatabaseExport>
 }
 
 #[async_trait]
 impl ApplicationCommandHandler for DatabaseHandler {
-    async fn handle_application_command(&mut self, data: &ApplicationCommandInteraction, _query_agent: &DBQueryAgent, _manager: &DBManager) -> Result<CommandResponseObject, String> {
-        let options = match utils::get_options(&data) {
-            Ok(o) => o,
-            Err(e) => return Err(format!("Error while getting options from command data: {e:?}"))
-        };
-
-        let action = match options.get(0) {
+    async fn handle_application_command(&mut self, data: &ApplicationCommandInteraction, query_agent: &DBQueryAgent, _manager: &DBManager) -> Result<CommandResponseObject, String> {
+        // `utils::get_options` skips past subcommands that have options of their own, so read the subcommand directly
+        let action = match data.data.options.first().and_then(|command| command.options.first()) {
             Some(a) => a,
             None => return Err(format!("Error while parsing options: Couldn't get subcommand data"))
         };
 
+        // Every subcommand needs the admin role, so the command stays off until one is set. Exports hold every owner's and co-owner's user ID
+        auth::require_admin_role(query_agent, data.member.as_ref(), &data.user).await?;
+
         match action.name.as_str() {
             /*"recreate" => {
                 Ok(CommandResponseObject::interactive(CreateComponents::default()
@@ -54,24 +60,17 @@ impl ApplicationCommandHandler for DatabaseHandler {
                     )
                 )
             },*/
-            "recreate" => {
-                Ok(CommandResponseObject::modal(
-                    CreateComponents::default()
-                        .create_action_row(|action_row| {
-                            action_row
-                                .create_input_text(|input_text| {
-                                    input_text
-                                        .custom_id("database-password-input")
-                                        .label("Enter password to delete database")
-                                        .placeholder("Enter password (this is not reversible!)")
-                                        .required(true)
-                                        .style(InputTextStyle::Short)
-                                })
-                        }).clone(),
-                        "database-password-modal".into()
-                    )
-                )
-            }
+            "recreate" => Ok(self.confirmation_modal(Some((RECREATE_CONFIRMATION, "This is not reversible!")), "database-recreate-modal".into())),
+            "export" => match self.db_password {
+                Some(_) => Ok(self.confirmation_modal(None, "database-export-modal".into())),
+                None => Self::export(query_agent).await
+            },
+            "import" => {
+                let export = self.read_import(&action.options).await?;
+                let token = self.pending_imports.insert(export);
+
+                Ok(self.confirmation_modal(Some((IMPORT_CONFIRMATION, "Current data will be replaced!")), utils::custom_id("database-import-modal", &token)))
+            },
             _ => {
                 Err("Error: couldn't find the requested subcommand".into())
             }
@@ -85,7 +84,24 @@ impl ApplicationCommandHandler for DatabaseHandler {
             CreateApplicationCommandOption::default()
                 .kind(CommandOptionType::SubCommand)
                 .name("recreate")
-                .description("Recreate the entire currency database, starting from scratch. DANGER, THIS IS NOT REVERSIBLE!!!")
+                .description("Recreate this server's currency database, starting from scratch. DANGER, THIS IS NOT REVERSIBLE!!!")
+                .clone(),
+            CreateApplicationCommandOption::default()
+                .kind(CommandOptionType::SubCommand)
+                .name("export")
+                .description("Download a backup of this server's currencies, transactions and records")
+                .clone(),
+            CreateApplicationCommandOption::default()
+                .kind(CommandOptionType::SubCommand)
+                .name("import")
+                .description("Replace this server's currency database with a backup from `/currency database export`")
+                .create_sub_option(|option| {
+                    option
+                        .kind(CommandOptionType::Attachment)
+                        .name("file")
+                        .description("A backup file from `/currency database export`")
+                        .required(true)
+                })
                 .clone()
         ]
     }
@@ -93,55 +109,178 @@ impl ApplicationCommandHandler for DatabaseHandler {
 
 #[async_trait]
 impl ModalSubmitHandler for DatabaseHandler {
-    async fn handle_modal_submit(&self, data: &ModalSubmitInteraction, _query_agent: &DBQueryAgent, manager: &DBManager) -> Result<CommandResponseObject, String> {
-
-        Ok(match data.data.custom_id.as_str() {
-            "recreate-database-confirm" => match manager.danger_recreate_database().await {
-                    Ok(_) => CommandResponseObject::interactive_with_feedback(CreateComponents::default(), "Database successfully recreated", format!("{0} recreated the Economist Bot database. All stored data has been lost.", data.user), true),
-                    Err(e) => return Err(format!("Error recreating database (this is probably a good thing): {e:?}"))
-                }
-
-            "recreate-database-cancel" => CommandResponseObject::interactive_with_feedback(CreateComponents::default(), "Cancelled deleting database (this is probably a good thing)", "", true),
-            "database-password-modal" => {
-                let components = data.data.components.clone();
-                
-                let action_row = match components.get(0) {
-                    Some(ar) => ar,
-                    None => return Err("Error while building response: could not get input data".into())
-                };
-
-                let input_component = match action_row.components.get(0) {
-                    Some(c) => c,
-                    None => return Err("Error while building response: could not get action row".into())
-                };
-
-                if let ActionRowComponent::InputText(input_text) = input_component {
-                    let password = input_text.value.clone();
-
-                    if password == self.db_password {
-                        match manager.danger_recreate_database().await {
-                            Ok(_) => return Ok(CommandResponseObject::interactive_with_feedback(CreateComponents::default(), "Database successfully recreated", format!("{0} recreated the Economist Bot database. All stored data has been lost.", data.user), true)),
-                            Err(e) => return Err(format!("Error recreating database (this is probably a good thing): {e:?}"))
-                        }
-                    } else {
-                        return Err("Error: incorrect password for database".into())
-                    }
-                } else {
-                    return Err("Error: couldn't find input text in components".into())
-                }
+    async fn handle_modal_submit(&mut self, data: &ModalSubmitInteraction, query_agent: &DBQueryAgent, manager: &DBManager) -> Result<CommandResponseObject, String> {
+        let (callsign, token) = utils::split_custom_id(data.data.custom_id.as_str());
+
+        // Taken before checking the password so a failed attempt can't be retried against the same upload
+        let import = match callsign {
+            "database-import-modal" => match token.and_then(|token| self.pending_imports.take(token)) {
+                Some(i) => Some(i),
+                None => return Err("This import has expired. Please run the command again.".into())
+            },
+            _ => None
+        };
+
+        // The role is checked again in case it was taken away while the modal was open
+        auth::require_admin_role(query_agent, data.member.as_ref(), &data.user).await?;
+
+        // Exporting doesn't destroy anything, so it only asks for the password
+        let confirmation = match callsign {
+            "database-import-modal" => Some(IMPORT_CONFIRMATION),
+            "database-export-modal" => None,
+            _ => Some(RECREATE_CONFIRMATION)
+        };
+        if let Some(confirmation) = confirmation {
+            if !Self::read_input(data, "database-confirm-input")?.trim().eq_ignore_ascii_case(confirmation) {
+                return Err(format!("Error: you must type `{confirmation}` to confirm, nothing has been changed"))
+            }
+        }
+        if let Some(password) = &self.db_password {
+            if &Self::read_input(data, "database-password-input")? != password {
+                return Err("Error: incorrect password for database".into())
+            }
+        }
+
+        if callsign == "database-export-modal" {
+            return Self::export(query_agent).await
+        }
+
+        // Whatever is about to be destroyed is exported first, and nothing is touched if that fails
+        let backup = match query_agent.export_database().await {
+            Ok(b) => b,
+            Err(e) => return Err(format!("Error while backing up database, nothing has been changed: {e:?}"))
+        };
+        let (filename, file) = Self::export_file(&backup)?;
+
+        match (callsign, import) {
+            ("database-recreate-modal", _) => match manager.danger_recreate_database(&data.user).await {
+                Ok(_) => Ok(CommandResponseObject::interactive_with_feedback(
+                    CreateComponents::default(),
+                    "Database successfully recreated. A backup of the previous data is attached.",
+                    format!("{0} recreated this server's Economist Bot database. All stored data has been lost.", data.user),
+                    true
+                ).with_file(filename, file)),
+                Err(e) => Err(format!("Error recreating database (this is probably a good thing): {e:?}"))
             },
-            _ => return Err("Error: unknown custom id".into())
-        })
+            ("database-import-modal", Some(import)) => match manager.import_database(&import, &data.user).await {
+                Ok(_) => Ok(CommandResponseObject::interactive_with_feedback(
+                    CreateComponents::default(),
+                    "Database successfully imported. A backup of the previous data is attached.",
+                    format!("{0} replaced this server's Economist Bot database with a backup from {1}: {2} currencies, {3} transactions and {4} records were restored.",
+                        data.user,
+                        import.exported_at.format("%Y-%m-%d %H:%M UTC"),
+                        import.currencies.len(),
+                        import.transactions.len(),
+                        import.records.len()
+                    ),
+                    true
+                ).with_file(filename, file)),
+                Err(e) => Err(format!("Error importing database, nothing has been changed: {e:?}"))
+            },
+            _ => Err("Error: unknown custom id".into())
+        }
     }
     fn get_pattern(&self) -> Vec<&str> {
-        vec!["recreate-database-confirm", "recreate-database-cancel", "database-password-modal"]
+        vec!["database-recreate-modal", "database-import-modal", "database-export-modal"]
     }
 }
 
 impl DatabaseHandler {
-    pub fn new(db_password: String) -> Self {
+    pub fn new(db_password: Option<String>) -> Self {
         DatabaseHandler {
-            db_password
+            db_password,
+            pending_imports: PendingActions::default()
+        }
+    }
+
+    // Asks for the confirmation phrase and its placeholder if given, and the database password too if one is set
+    fn confirmation_modal(&self, confirmation: Option<(&str, &str)>, custom_id: String) -> CommandResponseObject {
+        let mut components = CreateComponents::default();
+        if let Some((confirmation, placeholder)) = confirmation {
+            components.create_action_row(|action_row| {
+                action_row
+                    .create_input_text(|input_text| {
+                        input_text
+                            .custom_id("database-confirm-input")
+                            .label(format!("Type `{confirmation}` to confirm"))
+                            .placeholder(placeholder)
+                            .required(true)
+                            .style(InputTextStyle::Short)
+                    })
+            });
         }
+        if self.db_password.is_some() {
+            components.create_action_row(|action_row| {
+                action_row
+                    .create_input_text(|input_text| {
+                        input_text
+                            .custom_id("database-password-input")
+                            .label("Enter password")
+                            .required(true)
+                            .style(InputTextStyle::Short)
+                    })
+            });
+        }
+
+        CommandResponseObject::modal(components, custom_id)
+    }
+
+    fn read_input(data: &ModalSubmitInteraction, custom_id: &str) -> Result<String, String> {
+        let input = data.data.components.iter()
+            .flat_map(|action_row| action_row.components.iter())
+            .find_map(|component| match component {
+                ActionRowComponent::InputText(input_text) if input_text.custom_id == custom_id => Some(input_text.value.clone()),
+                _ => None
+            });
+
+        match input {
+            Some(i) => Ok(i),
+            None => Err("Error while building response: could not get input data".into())
+        }
+    }
+
+    async fn export(query_agent: &DBQueryAgent) -> Result<CommandResponseObject, String> {
+        let export = match query_agent.export_database().await {
+            Ok(e) => e,
+            Err(e) => return Err(format!("Error while exporting database: {e:?}"))
+        };
+
+        let (filename, file) = Self::export_file(&export)?;
+        Ok(CommandResponseObject::interactive(
+            CreateComponents::default(),
+            format!("Exported {} currencies, {} transactions and {} records", export.currencies.len(), export.transactions.len(), export.records.len()),
+            true
+        ).with_file(filename, file))
+    }
+
+    fn export_file(export: &DatabaseExport) -> Result<(String, Vec<u8>), String> {
+        match serde_json::to_vec_pretty(export) {
+            Ok(file) => Ok((format!("economist-{}.json", export.exported_at.format("%Y-%m-%d-%H%M%S")), file)),
+            Err(e) => Err(format!("Error while serialising database export: {e:?}"))
+        }
+    }
+
+    async fn read_import(&self, options: &[CommandDataOption]) -> Result<DatabaseExport, String> {
+        let attachment = match options.iter().find(|option| option.name.as_str() == "file").and_then(|option| option.resolved.clone()) {
+            Some(CommandDataOptionValue::Attachment(a)) => a,
+            _ => return Err("Error: no backup file specified".into())
+        };
+
+        let file = match attachment.download().await {
+            Ok(f) => f,
+            Err(e) => return Err(format!("Error while downloading `{}`: {e:?}", attachment.filename))
+        };
+
+        let export: DatabaseExport = match serde_json::from_slice(&file) {
+            Ok(e) => e,
+            Err(e) => return Err(format!("Error: `{}` is not a valid Economist Bot backup: {e}", attachment.filename))
+        };
+
+        let schema_version = crate::MIGRATOR.iter().map(|migration| migration.version).max().unwrap_or(0);
+        if export.schema_version > schema_version {
+            return Err(format!("Error: `{}` was exported from a newer version of Economist Bot and can't be imported", attachment.filename))
+        }
+
+        Ok(export)
     }
 }
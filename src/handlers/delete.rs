@@ -1,7 +1,10 @@
+use crate::auth;
 use crate::commands::manage::*;
 use crate::commands::query::*;
 use crate::types::*;
+use crate::ledger::{self, CurrencyEvent};
 use crate::utils;
+use crate::utils::pending::PendingActions;
 use crate::CommandResponseObject;
 use async_trait::async_trait;
 use serenity::model::application::interaction::message_component::MessageComponentInteraction;
@@ -13,13 +16,13 @@ use serenity::model::prelude::interaction::application_command::{
 };
 
 pub struct DeleteHandler {
-    currency_code: String,
-    currency_name: String
+    pending: PendingActions<CurrencyData>,
+    retention_days: i64 // 0 keeps archived currencies forever
 }
 
 #[async_trait]
 impl ApplicationCommandHandler for DeleteHandler {
-    async fn handle_application_command(&mut self, data: &ApplicationCommandInteraction, query_agent: &DBQueryAgent, _manager: &DBManager) -> Result<CommandResponseObject, String> {
+    async fn handle_application_command(&mut self, data: &ApplicationCommandInteraction, query_agent: &DBQueryAgent, manager: &DBManager) -> Result<CommandResponseObject, String> {
 
         let options = match utils::get_options(&data) {
             Ok(o) => o,
@@ -31,17 +34,9 @@ impl ApplicationCommandHandler for DeleteHandler {
             Err(e) => return Err(format!("Error while parsing options: {e:?}"))
         };
 
-        let currency_data = match query_agent.get_currency_data(currency_code.clone()).await {
-            Ok(data) => data,
-            Err(_e) => {
-                return Err(format!("Error: could not find the currency code `{currency_code}`"))
-            }
-        };
+        let currency_data = auth::authorize(query_agent, manager, currency_code, &data.user, CurrencyPermission::Delete).await?;
 
-        let user_name = data.user.name.clone();
-        if user_name != currency_data.owner {
-            return Err(format!("Error: you are not the owner of this currency, and therefore cannot modify it"))
-        };
+        let token = self.pending.insert(currency_data.clone());
 
         let components = CreateComponents::default()
             .create_action_row(|action_row| {
@@ -49,30 +44,27 @@ impl ApplicationCommandHandler for DeleteHandler {
                     .create_button(|button| {
                         button
                             .label("Confirm")
-                            .custom_id("delete-confirm")
+                            .custom_id(utils::custom_id("delete-confirm", &token))
                                 .style(ButtonStyle::Danger)
                         })
                         .create_button(|button| {
                             button
                                 .label("Cancel")
-                                .custom_id("delete-cancel")
+                                .custom_id(utils::custom_id("delete-cancel", &token))
                                 .style(ButtonStyle::Primary)
                         })
                 }).clone();
 
-        self.currency_code = currency_data.currency_code.clone();
-        self.currency_name = currency_data.currency_name.clone();
-
         Ok(CommandResponseObject::interactive(
             components,
-            format!("Confirm you really want to delete the currency **{}** `{}`?\n*This is not reversible*", currency_data.currency_name, currency_data.currency_code),
+            format!("Confirm you really want to delete the currency **{}** `{}`?\n*{}*", currency_data.currency_name, currency_data.currency_code, self.describe_retention()),
             true
         ))
 
     }
 
     fn get_name(&self) -> &str { "delete" }
-    fn get_description(&self) -> &str { "Delete a currency from the database" }
+    fn get_description(&self) -> &str { "Move a currency to the archive, where it can be restored with `/currency restore`" }
     fn get_option_kind(&self) -> CommandOptionType { CommandOptionType::SubCommand }
     fn register(&self) -> Vec<CreateApplicationCommandOption> {
         vec![
@@ -90,34 +82,35 @@ impl ApplicationCommandHandler for DeleteHandler {
 
 #[async_trait]
 impl InteractionResponseHandler for DeleteHandler {
-    async fn handle_interaction_response(&self, data: &MessageComponentInteraction, _query_agent: &DBQueryAgent, manager: &DBManager) -> Result<CommandResponseObject, String> {
+    async fn handle_interaction_response(&mut self, data: &MessageComponentInteraction, _query_agent: &DBQueryAgent, manager: &DBManager) -> Result<CommandResponseObject, String> {
 
-        let confirm = match data.data.custom_id.as_str() {
-            "delete-confirm" => true,
-            _ => false
+        let (callsign, token) = utils::split_custom_id(data.data.custom_id.as_str());
+        let currency = match token.and_then(|token| self.pending.take(token)) {
+            Some(c) => c,
+            None => return Err("This deletion has expired or has already been completed. Please run the command again.".into())
         };
 
-        if confirm {
-            match manager.remove_currency(self.currency_code.clone()).await {
-                Ok(_) => Ok(CommandResponseObject::interactive_with_feedback(
+        if callsign == "delete-confirm" {
+            match manager.archive_currency(currency.currency_code.clone(), &data.user).await {
+                Ok(archived) => Ok(CommandResponseObject::interactive_with_feedback(
                     CreateComponents::default(),
                     format!(
-                        "Successfully deleted currency **{}** `{}`",
-                        self.currency_name, self.currency_code
+                        "Successfully deleted currency **{}** `{}`. {}",
+                        currency.currency_name, currency.currency_code, self.describe_retention()
                     ), 
                     format!("{} deleted currency **{}** `{}`", 
                         data.user,
-                        self.currency_name, 
-                        self.currency_code
+                        currency.currency_name, 
+                        currency.currency_code
                     ), 
                     true
-                )),
-                Err(e) => Err(format!("Error removing currency from database: {e:?}"))
+                ).with_ledger(ledger::currency_event(CurrencyEvent::Deleted, &archived, &data.user))),
+                Err(e) => Err(format!("Error archiving currency: {e:?}"))
             }
         } else {
             Ok(CommandResponseObject::interactive_with_feedback(
                 CreateComponents::default(),
-                format!("Will not delete currency **{}** `{}`", self.currency_name, self.currency_code),
+                format!("Will not delete currency **{}** `{}`", currency.currency_name, currency.currency_code),
                 "",
                 true
             ))
@@ -130,10 +123,18 @@ impl InteractionResponseHandler for DeleteHandler {
 }
 
 impl DeleteHandler {
-    pub fn new() -> Self {
+    pub fn new(retention_days: i64) -> Self {
         DeleteHandler {
-            currency_code: String::new(),
-            currency_name: String::new()
+            pending: PendingActions::default(),
+            retention_days
+        }
+    }
+
+    fn describe_retention(&self) -> String {
+        match self.retention_days {
+            0 => "It can be restored at any time with `/currency restore`".into(),
+            1 => "It can be restored with `/currency restore` for 1 day, then it will be permanently deleted".into(),
+            days => format!("It can be restored with `/currency restore` for {days} days, then it will be permanently deleted")
         }
     }
 
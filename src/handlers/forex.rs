@@ -0,0 +1,132 @@
+use crate::commands::manage::*;
+use crate::commands::query::*;
+use crate::types::*;
+use crate::utils;
+use crate::CommandResponseObject;
+use async_trait::async_trait;
+use serenity::builder::CreateApplicationCommandOption;
+use serenity::model::prelude::command::CommandOptionType;
+use serenity::model::prelude::interaction::application_command::{
+    ApplicationCommandInteraction, CommandDataOption, CommandDataOptionValue,
+};
+
+pub struct ForexHandler {}
+
+#[async_trait]
+impl ApplicationCommandHandler for ForexHandler {
+    async fn handle_application_command(&mut self, data: &ApplicationCommandInteraction, query_agent: &DBQueryAgent, _manager: &DBManager) -> Result<CommandResponseObject, String> {
+        let options = match utils::get_options(data) {
+            Ok(o) => o,
+            Err(e) => return Err(format!("Error while parsing options: {e:?}"))
+        };
+
+        let (from_code, to_code, amount) = match self.parse_options(&options) {
+            Ok(o) => o,
+            Err(e) => return Err(format!("Error while parsing options: {e}"))
+        };
+
+        let exchange_rate = match query_agent.get_exchange_rate(from_code.clone(), to_code.clone()).await {
+            Ok(r) => r,
+            Err(e) => return Err(format!("Error while looking up currencies `{from_code}` and `{to_code}`: {e:?}"))
+        };
+
+        let (from, to) = (&exchange_rate.from, &exchange_rate.to);
+
+        let rate_display = match exchange_rate.rate {
+            Some(rate) => format!("`1 {0}` = `{rate:.4} {1}`", from.currency_code, to.currency_code),
+            None => format!("`1 {0}` = *not convertible, {1} has no value*", from.currency_code, to.currency_code)
+        };
+
+        let inverse_display = match exchange_rate.inverse_rate {
+            Some(rate) => format!("`1 {0}` = `{rate:.4} {1}`", to.currency_code, from.currency_code),
+            None => format!("`1 {0}` = *not convertible, {1} has no value*", to.currency_code, from.currency_code)
+        };
+
+        let mut description = format!(
+            "> **{0}** (*{1}*): `{2:.3} ingot / {3}`\n> **{4}** (*{5}*): `{6:.3} ingot / {7}`\n\n**Exchange rate**\n> {rate_display}\n> {inverse_display}",
+            from.currency_name,
+            from.state,
+            from.value,
+            from.currency_code,
+            to.currency_name,
+            to.state,
+            to.value,
+            to.currency_code
+        );
+
+        if let Some(amount) = amount {
+            description += match exchange_rate.convert(amount) {
+                Some(converted) => format!("\n\n**Conversion**\n> `{amount:.2} {0}` = `{converted:.2} {1}`", from.currency_code, to.currency_code),
+                None => format!("\n\n**Conversion**\n> `{amount:.2} {0}` cannot be converted to {1}", from.currency_code, to.currency_code)
+            }.as_str();
+        }
+
+        let embed = serenity::builder::CreateEmbed::default()
+            .title(format!("{} to {}", from.currency_name, to.currency_name))
+            .description(description)
+            .clone();
+
+        Ok(CommandResponseObject::embed(embed))
+    }
+
+    fn get_name(&self) -> &str { "forex" }
+    fn get_description(&self) -> &str { "Compare two currencies and convert between them" }
+    fn get_option_kind(&self) -> CommandOptionType { CommandOptionType::SubCommand }
+    fn register(&self) -> Vec<CreateApplicationCommandOption> {
+        vec![
+            CreateApplicationCommandOption::default()
+                .kind(CommandOptionType::String)
+                .name("from")
+                .description("Three-letter code of the currency to convert from")
+                .min_length(3)
+                .max_length(3)
+                .required(true)
+                .clone(),
+            CreateApplicationCommandOption::default()
+                .kind(CommandOptionType::String)
+                .name("to")
+                .description("Three-letter code of the currency to convert to")
+                .min_length(3)
+                .max_length(3)
+                .required(true)
+                .clone(),
+            CreateApplicationCommandOption::default()
+                .kind(CommandOptionType::Number)
+                .name("amount")
+                .description("Amount of the first currency to convert")
+                .min_number_value(0.0)
+                .clone()
+        ]
+    }
+}
+
+impl ForexHandler {
+    pub fn new() -> Self {
+        ForexHandler {}
+    }
+
+    fn parse_options(&self, options: &Vec<CommandDataOption>) -> Result<(String, String, Option<f64>), String> {
+        let mut from_code = None;
+        let mut to_code = None;
+        let mut amount = None;
+
+        for option in options {
+            match option.name.as_str() {
+                "from" => if let Some(CommandDataOptionValue::String(code)) = option.resolved.clone() { from_code = Some(code) },
+                "to" => if let Some(CommandDataOptionValue::String(code)) = option.resolved.clone() { to_code = Some(code) },
+                "amount" => if let Some(CommandDataOptionValue::Number(n)) = option.resolved.clone() {
+                    if n < 0.0 {
+                        return Err("Can't convert a negative amount".into())
+                    }
+                    amount = Some(n)
+                },
+                _ => {}
+            }
+        }
+
+        match (from_code, to_code) {
+            (Some(from), Some(to)) => Ok((from, to, amount)),
+            _ => Err("Error: two currency codes must be specified".into())
+        }
+    }
+}
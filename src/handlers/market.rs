@@ -0,0 +1,178 @@
+use crate::auth;
+use crate::commands::manage::*;
+use crate::commands::query::*;
+use crate::types::*;
+use crate::utils;
+use crate::workers::records::RecordWorkers;
+use crate::CommandResponseObject;
+use async_trait::async_trait;
+use chrono::{NaiveTime, Utc, Weekday};
+use chrono_tz::Tz;
+use serenity::builder::CreateApplicationCommandOption;
+use serenity::model::prelude::command::CommandOptionType;
+use serenity::model::prelude::interaction::application_command::{
+    ApplicationCommandInteraction, CommandDataOption, CommandDataOptionValue,
+};
+use tracing::error;
+
+pub struct MarketHandler {
+    record_workers: RecordWorkers
+}
+
+#[async_trait]
+impl ApplicationCommandHandler for MarketHandler {
+    async fn handle_application_command(&mut self, data: &ApplicationCommandInteraction, query_agent: &DBQueryAgent, manager: &DBManager) -> Result<CommandResponseObject, String> {
+        let options = match utils::get_options(data) {
+            Ok(o) => o,
+            Err(e) => return Err(format!("Error while getting options from command data: {e:?}"))
+        };
+
+        let mut config = match query_agent.get_market_config().await {
+            Ok(c) => c,
+            Err(e) => return Err(format!("Error getting market hours: {e:?}"))
+        };
+
+        // Without any options this just shows the current hours, which anyone can do
+        if options.is_empty() {
+            return Ok(CommandResponseObject::text(Self::describe_config(&config)))
+        }
+
+        auth::require_guild_admin(data)?;
+        self.parse_options(&options, &mut config)?;
+
+        let config = match manager.set_market_config(&config, &data.user).await {
+            Ok(c) => c,
+            Err(e) => return Err(format!("Error saving market hours: {e:?}"))
+        };
+
+        if let Err(e) = self.record_workers.send(query_agent.guild_id(), WorkerMessage::Reschedule) {
+            error!("Couldn't tell the record worker about new market hours: {e:?}");
+        }
+
+        Ok(CommandResponseObject::text(format!("{} updated the market hours\n{}", data.user, Self::describe_config(&config))))
+    }
+
+    fn get_name(&self) -> &str { "market" }
+    fn get_description(&self) -> &str { "View or change when the market opens and closes each day" }
+    fn get_option_kind(&self) -> CommandOptionType { CommandOptionType::SubCommand }
+    fn register(&self) -> Vec<CreateApplicationCommandOption> {
+        vec![
+            CreateApplicationCommandOption::default()
+                .kind(CommandOptionType::String)
+                .name("open")
+                .description("Local time the market opens, e.g. 06:00")
+                .clone(),
+            CreateApplicationCommandOption::default()
+                .kind(CommandOptionType::String)
+                .name("close")
+                .description("Local time the market closes, e.g. 18:00 (earlier than opening to close the next day)")
+                .clone(),
+            CreateApplicationCommandOption::default()
+                .kind(CommandOptionType::String)
+                .name("timezone")
+                .description("Timezone the market hours are in, e.g. Europe/London")
+                .clone(),
+            CreateApplicationCommandOption::default()
+                .kind(CommandOptionType::String)
+                .name("days")
+                .description("Days the market opens on, e.g. mon-fri or mon,wed,fri or all")
+                .clone(),
+            CreateApplicationCommandOption::default()
+                .kind(CommandOptionType::Integer)
+                .name("interval")
+                .description("Minutes between snapshots of every currency's value while trading, 0 for none (default: 60)")
+                .min_int_value(0)
+                .max_int_value(1440)
+                .clone()
+        ]
+    }
+}
+
+impl MarketHandler {
+    pub fn new(record_workers: RecordWorkers) -> Self {
+        MarketHandler {
+            record_workers
+        }
+    }
+
+    fn parse_options(&self, options: &[CommandDataOption], config: &mut MarketConfig) -> Result<(), String> {
+        for option in options {
+            if let Some(CommandDataOptionValue::Integer(interval)) = option.resolved {
+                config.snapshot_interval_minutes = interval as i32;
+                continue
+            }
+            let Some(CommandDataOptionValue::String(value)) = option.resolved.clone() else {
+                continue
+            };
+            let value = value.trim();
+            match option.name.as_str() {
+                "open" => config.open_time = Self::parse_time(value)?,
+                "close" => config.close_time = Self::parse_time(value)?,
+                "timezone" => match value.parse::<Tz>() {
+                    Ok(tz) => config.timezone = tz.name().into(),
+                    Err(_) => return Err(format!("Error: `{value}` is not a known timezone, use a name like `Europe/London` or `America/New_York`"))
+                },
+                "days" => config.trading_days = Self::parse_days(value)?,
+                _ => {}
+            }
+        }
+
+        Ok(())
+    }
+
+    fn parse_time(value: &str) -> Result<NaiveTime, String> {
+        NaiveTime::parse_from_str(value, "%H:%M")
+            .map_err(|_| format!("Error: `{value}` is not a valid time, use 24-hour `HH:MM` like `18:00`"))
+    }
+
+    // Comma separated days or ranges of days, where ranges can wrap around the weekend, e.g. `fri-mon`
+    fn parse_days(value: &str) -> Result<i16, String> {
+        if value.eq_ignore_ascii_case("all") {
+            return Ok(0b111_1111)
+        }
+
+        let parse_day = |day: &str| day.trim().parse::<Weekday>()
+            .map_err(|_| format!("Error: `{}` is not a day of the week", day.trim()));
+
+        let mut days = 0;
+        for part in value.split(',') {
+            let (first, last) = match part.split_once('-') {
+                Some((first, last)) => (parse_day(first)?, parse_day(last)?),
+                None => (parse_day(part)?, parse_day(part)?)
+            };
+
+            let mut day = first;
+            loop {
+                days |= 1 << day.num_days_from_monday();
+                if day == last {
+                    break
+                }
+                day = day.succ();
+            }
+        }
+
+        Ok(days)
+    }
+
+    fn describe_config(config: &MarketConfig) -> String {
+        let mut description = format!(
+            "> Opens: `{}`\n> Closes: `{}`\n> Timezone: `{}`\n> Trading days: {}\n> Snapshots: {}",
+            config.open_time.format("%H:%M"),
+            config.close_time.format("%H:%M"),
+            config.timezone,
+            config.describe_days(),
+            match config.snapshot_interval_minutes {
+                0 => "after transactions only".to_string(),
+                minutes => format!("every {minutes} minutes and after transactions")
+            }
+        );
+
+        match config.next_session(Utc::now()) {
+            Some(session) if session.opens_at <= Utc::now() => description += &format!("\n> The market is open until <t:{}:f>", session.closes_at.timestamp()),
+            Some(session) => description += &format!("\n> The market opens next at <t:{}:f>", session.opens_at.timestamp()),
+            None => description += "\n> The market is closed until trading days are set"
+        }
+
+        description
+    }
+}
@@ -1,9 +1,21 @@
+pub mod admin;
+pub mod audit;
 pub mod circulation;
+pub mod compare;
 pub mod create;
 pub mod database;
 pub mod delete;
+pub mod forex;
+pub mod ledger;
 pub mod list;
+pub mod market;
 pub mod modify;
+pub mod owners;
 pub mod records;
+pub mod report;
 pub mod reserve;
+pub mod restore;
+pub mod revert;
+pub mod transactions;
 pub mod view;
+pub mod worker;
@@ -1,6 +1,8 @@
+use crate::auth;
 use crate::commands::manage::*;
 use crate::commands::query::*;
 use crate::types::*;
+use crate::ledger::{self, CurrencyEvent};
 use crate::utils;
 use crate::CommandResponseObject;
 use async_trait::async_trait;
@@ -48,37 +50,28 @@ impl ApplicationCommandHandler for ModifyHandler {
         match action.as_str() {
             "code" => {
                 if let Some(old_code) = options.old_code {
-                    match self.verify_user(query_agent, &old_code, data).await {
-                        Ok(verified) => if !verified { return Err("Error: you are not the owner of this currency, and therefore cannot modify it".into()) },
-                        Err(e) => return Err(e)
-                    }
+                    auth::authorize(query_agent, manager, old_code.clone(), &data.user, CurrencyPermission::Metadata).await?;
 
                     if let Some(new_code) = options.new_code {
-                        final_data = manager.modify_currency_meta(old_code, ModifyMetaType::Code, new_code).await;
+                        final_data = manager.modify_currency_meta(old_code, ModifyMetaType::Code, new_code, &data.user).await;
                     }
                 }
             },
             "state" => {
                 if let Some(code) = options.code {
-                    match self.verify_user(query_agent, &code, data).await {
-                        Ok(verified) => if !verified { return Err("Error: you are not the owner of this currency, and therefore cannot modify it".into()) },
-                        Err(e) => return Err(e)
-                    }
+                    auth::authorize(query_agent, manager, code.clone(), &data.user, CurrencyPermission::Metadata).await?;
 
                     if let Some(state) = options.state {
-                        final_data = manager.modify_currency_meta(code, ModifyMetaType::State, state).await;
+                        final_data = manager.modify_currency_meta(code, ModifyMetaType::State, state, &data.user).await;
                     }
                 }
             },
             "name" => {
                 if let Some(code) = options.code {
-                    match self.verify_user(query_agent, &code, data).await {
-                        Ok(verified) => if !verified { return Err("Error: you are not the owner of this currency, and therefore cannot modify it".into()) },
-                        Err(e) => return Err(e)
-                    }
+                    auth::authorize(query_agent, manager, code.clone(), &data.user, CurrencyPermission::Metadata).await?;
 
                     if let Some(name) = options.name {
-                        final_data = manager.modify_currency_meta(code, ModifyMetaType::Name, name).await
+                        final_data = manager.modify_currency_meta(code, ModifyMetaType::Name, name, &data.user).await
                     }
                 }
             },
@@ -113,7 +106,9 @@ impl ApplicationCommandHandler for ModifyHandler {
                 modification
             ),
             false
-        ))
+        ).with_ledger(ledger::currency_event(CurrencyEvent::Modified, &currency_data, &data.user)
+            .field("Change", modification, false)
+            .clone()))
     }
 
     fn get_name(&self) -> &str { "modify" }
@@ -191,19 +186,6 @@ impl ModifyHandler {
         ModifyHandler {}
     }
 
-    async fn verify_user(&self, query_agent: &DBQueryAgent, code: &String, data: &ApplicationCommandInteraction) -> Result<bool, String> {
-        let currency_data = match query_agent.get_currency_data(code.clone()).await {
-            Ok(d) => d,
-            Err(e) => return Err(format!("Error while looking up currency `{code}`: {e:?}"))
-        };
-
-        if currency_data.owner == data.user.name {
-            Ok(true)
-        } else {
-            Ok(false)
-        }
-    }
-
     fn parse_options(&self, options: &Vec<CommandDataOption>) -> Result<ModifyOptions, String> {
 
         let mut opts = ModifyOptions {
@@ -0,0 +1,313 @@
+use crate::auth;
+use crate::commands::manage::*;
+use crate::commands::query::*;
+use crate::ledger::{self, CurrencyEvent};
+use crate::types::*;
+use crate::utils;
+use crate::utils::pending::PendingActions;
+use crate::CommandResponseObject;
+use async_trait::async_trait;
+use serenity::model::application::interaction::message_component::MessageComponentInteraction;
+use serenity::model::application::component::ButtonStyle;
+use serenity::model::user::User;
+use serenity::builder::{CreateComponents, CreateApplicationCommandOption};
+use serenity::model::prelude::command::CommandOptionType;
+use serenity::model::prelude::interaction::application_command::{
+    ApplicationCommandInteraction, CommandDataOption, CommandDataOptionValue,
+};
+
+pub struct OwnersHandler {
+    pending: PendingActions<(CurrencyData, User)>
+}
+
+struct OwnersOptions {
+    code: Option<String>,
+    user: Option<User>,
+    reserve: bool,
+    circulation: bool,
+    metadata: bool,
+    delete: bool
+}
+
+#[async_trait]
+impl ApplicationCommandHandler for OwnersHandler {
+    async fn handle_application_command(&mut self, data: &ApplicationCommandInteraction, query_agent: &DBQueryAgent, manager: &DBManager) -> Result<CommandResponseObject, String> {
+        let option_data = match utils::get_options(data) {
+            Ok(o) => o,
+            Err(e) => return Err(format!("Error while getting options from command data: {e:?}"))
+        };
+
+        let options = self.parse_options(&option_data);
+
+        let action = match data.data.options.first().and_then(|cmd| cmd.options.first()) {
+            Some(a) => a.name.clone(),
+            None => return Err("Error while parsing options: Couldn't get which sub-subcommand to run".into())
+        };
+
+        let (Some(code), Some(user)) = (options.code.clone(), options.user.clone()) else {
+            return Err("Error: a currency code and a user must be specified".into())
+        };
+
+        let currency_data = match action.as_str() {
+            "transfer" => auth::authorize_transfer(query_agent, manager, code, data).await?,
+            _ => auth::authorize(query_agent, manager, code, &data.user, CurrencyPermission::Owner).await?
+        };
+
+        if user.bot {
+            return Err("Error: bots can't own or manage currencies".into())
+        }
+
+        match action.as_str() {
+            "add" => {
+                if currency_data.is_owned_by(&user) {
+                    return Err(format!("Error: {user} already owns this currency"))
+                }
+
+                let manager_data = match manager.set_manager(&ManagerData {
+                    currency_id: currency_data.currency_id,
+                    user_id: user.id.0 as i64,
+                    can_reserve: options.reserve,
+                    can_circulation: options.circulation,
+                    can_metadata: options.metadata,
+                    can_delete: options.delete
+                }, &data.user).await {
+                    Ok(m) => m,
+                    Err(e) => return Err(format!("Error while adding co-owner: {e:?}"))
+                };
+
+                Ok(CommandResponseObject::interactive_with_feedback(
+                    CreateComponents::default(),
+                    format!("Successfully updated {user}'s permissions for **{}** `{}`", currency_data.currency_name, currency_data.currency_code),
+                    format!("{0} made {user} a co-owner of **{1}** `{2}`:\n> {3}",
+                        data.user,
+                        currency_data.currency_name,
+                        currency_data.currency_code,
+                        Self::describe_permissions(&manager_data)
+                    ),
+                    false
+                ).with_ledger(ledger::currency_event(CurrencyEvent::CoOwnerSet, &currency_data, &data.user)
+                    .field("Co-owner", user.to_string(), true)
+                    .field("Permissions", Self::describe_permissions(&manager_data), false)
+                    .clone()))
+            },
+            "remove" => {
+                match manager.remove_manager(currency_data.currency_id, user.id.0 as i64, &data.user).await {
+                    Ok(true) => Ok(CommandResponseObject::interactive_with_feedback(
+                        CreateComponents::default(),
+                        format!("Successfully removed {user} as a co-owner of **{}** `{}`", currency_data.currency_name, currency_data.currency_code),
+                        format!("{0} removed {user} as a co-owner of **{1}** `{2}`", data.user, currency_data.currency_name, currency_data.currency_code),
+                        false
+                    ).with_ledger(ledger::currency_event(CurrencyEvent::CoOwnerRemoved, &currency_data, &data.user)
+                        .field("Co-owner", user.to_string(), true)
+                        .clone())),
+                    Ok(false) => Err(format!("Error: {user} is not a co-owner of this currency")),
+                    Err(e) => Err(format!("Error while removing co-owner: {e:?}"))
+                }
+            },
+            "transfer" => {
+                if currency_data.is_owned_by(&user) {
+                    return Err(format!("Error: {user} already owns this currency"))
+                }
+
+                let prompt = match currency_data.owner_id {
+                    Some(_) => format!("Confirm you really want to transfer ownership of **{}** `{}` to {user}?\n*You will lose all control of this currency unless they make you a co-owner*", currency_data.currency_name, currency_data.currency_code),
+                    None => format!("Confirm you want to make {user} the owner of **{}** `{}`?\n*It has no owner on record, so nobody can manage it until one is assigned*", currency_data.currency_name, currency_data.currency_code)
+                };
+                let token = self.pending.insert((currency_data, user));
+
+                let components = CreateComponents::default()
+                    .create_action_row(|action_row| {
+                        action_row
+                            .create_button(|button| {
+                                button
+                                    .label("Confirm")
+                                    .style(ButtonStyle::Danger)
+                                    .custom_id(utils::custom_id("owners-transfer-confirm", &token))
+                            })
+                            .create_button(|button| {
+                                button
+                                    .label("Cancel")
+                                    .style(ButtonStyle::Primary)
+                                    .custom_id(utils::custom_id("owners-transfer-cancel", &token))
+                            })
+                    }).clone();
+
+                Ok(CommandResponseObject::interactive(components, prompt, true))
+            },
+            _ => Err("Error: couldn't find the requested subcommand".into())
+        }
+    }
+
+    fn get_name(&self) -> &str { "owners" }
+    fn get_description(&self) -> &str { "Manage the owner and co-owners of a currency" }
+    fn register(&self) -> Vec<CreateApplicationCommandOption> {
+        vec![
+            CreateApplicationCommandOption::default()
+                .kind(CommandOptionType::SubCommand)
+                .name("add")
+                .description("Add a co-owner to a currency, or change their permissions")
+                .create_sub_option(|option| {
+                    option
+                        .kind(CommandOptionType::String)
+                        .name("code")
+                        .description("Three-letter currency code")
+                        .min_length(3)
+                        .max_length(3)
+                        .required(true)
+                })
+                .create_sub_option(|option| {
+                    option
+                        .kind(CommandOptionType::User)
+                        .name("user")
+                        .description("The user to make a co-owner")
+                        .required(true)
+                })
+                .create_sub_option(|option| {
+                    option
+                        .kind(CommandOptionType::Boolean)
+                        .name("reserve")
+                        .description("Allow managing gold reserves (default: yes)")
+                })
+                .create_sub_option(|option| {
+                    option
+                        .kind(CommandOptionType::Boolean)
+                        .name("circulation")
+                        .description("Allow managing circulation (default: yes)")
+                })
+                .create_sub_option(|option| {
+                    option
+                        .kind(CommandOptionType::Boolean)
+                        .name("metadata")
+                        .description("Allow modifying the currency name, code and nation/state (default: no)")
+                })
+                .create_sub_option(|option| {
+                    option
+                        .kind(CommandOptionType::Boolean)
+                        .name("delete")
+                        .description("Allow deleting the currency (default: no)")
+                }).clone(),
+            CreateApplicationCommandOption::default()
+                .kind(CommandOptionType::SubCommand)
+                .name("remove")
+                .description("Remove a co-owner from a currency")
+                .create_sub_option(|option| {
+                    option
+                        .kind(CommandOptionType::String)
+                        .name("code")
+                        .description("Three-letter currency code")
+                        .min_length(3)
+                        .max_length(3)
+                        .required(true)
+                })
+                .create_sub_option(|option| {
+                    option
+                        .kind(CommandOptionType::User)
+                        .name("user")
+                        .description("The co-owner to remove")
+                        .required(true)
+                }).clone(),
+            CreateApplicationCommandOption::default()
+                .kind(CommandOptionType::SubCommand)
+                .name("transfer")
+                .description("Transfer ownership of a currency to another user")
+                .create_sub_option(|option| {
+                    option
+                        .kind(CommandOptionType::String)
+                        .name("code")
+                        .description("Three-letter currency code")
+                        .min_length(3)
+                        .max_length(3)
+                        .required(true)
+                })
+                .create_sub_option(|option| {
+                    option
+                        .kind(CommandOptionType::User)
+                        .name("user")
+                        .description("The new owner of the currency")
+                        .required(true)
+                }).clone()
+        ]
+    }
+}
+
+#[async_trait]
+impl InteractionResponseHandler for OwnersHandler {
+    async fn handle_interaction_response(&mut self, data: &MessageComponentInteraction, _query_agent: &DBQueryAgent, manager: &DBManager) -> Result<CommandResponseObject, String> {
+        let (callsign, token) = utils::split_custom_id(data.data.custom_id.as_str());
+        let (currency_data, new_owner) = match token.and_then(|token| self.pending.take(token)) {
+            Some(p) => p,
+            None => return Err("This transfer has expired or has already been completed. Please run the command again.".into())
+        };
+
+        if callsign != "owners-transfer-confirm" {
+            return Ok(CommandResponseObject::interactive_with_feedback(
+                CreateComponents::default(),
+                format!("Will not transfer ownership of **{}** `{}`", currency_data.currency_name, currency_data.currency_code),
+                "",
+                true
+            ))
+        }
+
+        match manager.transfer_ownership(currency_data.currency_id, new_owner.name.clone(), new_owner.id.0 as i64, &data.user).await {
+            Ok(currency_data) => Ok(CommandResponseObject::interactive_with_feedback(
+                CreateComponents::default(),
+                format!("Successfully transferred ownership of **{}** `{}`", currency_data.currency_name, currency_data.currency_code),
+                format!("{0} transferred ownership of **{1}** `{2}` to {new_owner}", data.user, currency_data.currency_name, currency_data.currency_code),
+                true
+            ).with_ledger(ledger::currency_event(CurrencyEvent::Transferred, &currency_data, &data.user))),
+            Err(e) => Err(format!("Error while transferring ownership: {e:?}"))
+        }
+    }
+
+    fn get_pattern(&self) -> Vec<&str> {
+        vec!["owners-transfer-confirm", "owners-transfer-cancel"]
+    }
+}
+
+impl OwnersHandler {
+    pub fn new() -> Self {
+        OwnersHandler {
+            pending: PendingActions::default()
+        }
+    }
+
+    fn parse_options(&self, options: &Vec<CommandDataOption>) -> OwnersOptions {
+        let mut opts = OwnersOptions {
+            code: None,
+            user: None,
+            reserve: true,
+            circulation: true,
+            metadata: false,
+            delete: false
+        };
+
+        for option in options {
+            match (option.name.as_str(), option.resolved.clone()) {
+                ("code", Some(CommandDataOptionValue::String(c))) => opts.code = Some(c),
+                ("user", Some(CommandDataOptionValue::User(u, _))) => opts.user = Some(u),
+                ("reserve", Some(CommandDataOptionValue::Boolean(b))) => opts.reserve = b,
+                ("circulation", Some(CommandDataOptionValue::Boolean(b))) => opts.circulation = b,
+                ("metadata", Some(CommandDataOptionValue::Boolean(b))) => opts.metadata = b,
+                ("delete", Some(CommandDataOptionValue::Boolean(b))) => opts.delete = b,
+                _ => {}
+            }
+        }
+
+        opts
+    }
+
+    fn describe_permissions(manager_data: &ManagerData) -> String {
+        let permissions: Vec<&str> = [
+            (manager_data.can_reserve, "Gold reserves"),
+            (manager_data.can_circulation, "Circulation"),
+            (manager_data.can_metadata, "Name, code and nation/state"),
+            (manager_data.can_delete, "Deletion")
+        ].iter().filter(|(allowed, _)| *allowed).map(|(_, name)| *name).collect();
+
+        if permissions.is_empty() {
+            "Permissions: *none*".into()
+        } else {
+            format!("Permissions: {}", permissions.join(", "))
+        }
+    }
+}
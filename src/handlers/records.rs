@@ -31,7 +31,7 @@ impl ApplicationCommandHandler for RecordsHandler {
 			Err(e) => return Err(format!("Error while getting currency data: {e:?}"))
 		};
 
-		let records = match query_agent.get_reports(number, currency_code.clone()).await {
+		let records = match query_agent.get_reports(Some(number), currency_code.clone(), None).await {
 			Ok(r) => r,
 			Err(e) => return Err(format!("Error while looking up currency records: {e:?}"))
 		};
@@ -39,11 +39,14 @@ impl ApplicationCommandHandler for RecordsHandler {
 		let currency_string = format!("[\u{001b}[36m{0}\u{001b}[0m] \u{001b}[1m{1}\u{001b}[0m\n", currency.currency_code, currency.currency_name);
 
 		let mut final_string = format!("```ansi\nRecord list for {}\n", currency_string);
-        final_string += "┏━━━━━━━━━━┳━━━━━━━━━━━━━━━━━┳━━━━━━━━━━━━━━━━━┳━━━━━━━━━━━━━━━━┳━━━━━━━━━━━━━━┓\n";
-        final_string += "┃Date      ┃Value at Opening ┃Value at Closing ┃Change in Value ┃Performance   ┃\n";
-        final_string += "┣━━━━━━━━━━╋━━━━━━━━━━━━━━━━━╋━━━━━━━━━━━━━━━━━╋━━━━━━━━━━━━━━━━╋━━━━━━━━━━━━━━┫";
+        final_string += "┏━━━━━━━━━━┳━━━━━━━━━━━━━━━━━┳━━━━━━━━━━━━━━━━━┳━━━━━━━━┳━━━━━━━━┳━━━━━━━━━━━━━━━━┳━━━━━━━━━━━━━━┓\n";
+        final_string += "┃Date      ┃Value at Opening ┃Value at Closing ┃High    ┃Low     ┃Change in Value ┃Performance   ┃\n";
+        final_string += "┣━━━━━━━━━━╋━━━━━━━━━━━━━━━━━╋━━━━━━━━━━━━━━━━━╋━━━━━━━━╋━━━━━━━━╋━━━━━━━━━━━━━━━━╋━━━━━━━━━━━━━━┫";
 
+        let any_interpolated = records.iter().any(|record| record.interpolated);
 		for record in records {
+            // Estimated records are dated in yellow
+            let date_color = if record.interpolated { "\u{001b}[33m" } else { "" };
             let performance_description = if record.growth == 0 {
                     "Holding Steady"
                 } else if record.growth < 0 {
@@ -59,22 +62,29 @@ impl ApplicationCommandHandler for RecordsHandler {
                     "\u{001b}[1;32m"
                 };
 			final_string += format!(
-				"\n┃{0: <10.10}┃\u{001b}[1;35m{1: <5.3}\u{001b}[0m ingot / {5}┃\u{001b}[1;35m{2: <5.3}\u{001b}[0m ingot / {5}┃{performance_color}{3: <16.3}\u{001b}[0m┃{performance_color}{4: <14.14}\u{001b}[0m┃",
+				"\n┃{date_color}{0: <10.10}\u{001b}[0m┃\u{001b}[1;35m{1: <5.3}\u{001b}[0m ingot / {5}┃\u{001b}[1;35m{2: <5.3}\u{001b}[0m ingot / {5}┃{6: <8.3}┃{7: <8.3}┃{performance_color}{3: <16.3}\u{001b}[0m┃{performance_color}{4: <14.14}\u{001b}[0m┃",
                 record.record_date,
                 record.opening_value,
                 record.closing_value,
                 record.delta_value,
                 performance_description,
-                currency.currency_code
+                currency.currency_code,
+                record.high(),
+                record.low()
 			).as_str()
 		};
 		// Currency name: 40 chars
 		// Date: 10 chars
 		// Opening and Closing Values; 8 chars
+		// High and Low: 8 chars
 		// Change in value: 9 chars
 		// Growth: 'In Decline' 'Holding Steady' 'Gaining Value' 14 chars
         
-        final_string += "\n┗━━━━━━━━━━┻━━━━━━━━━━━━━━━━━┻━━━━━━━━━━━━━━━━━┻━━━━━━━━━━━━━━━━┻━━━━━━━━━━━━━━┛```";
+        final_string += "\n┗━━━━━━━━━━┻━━━━━━━━━━━━━━━━━┻━━━━━━━━━━━━━━━━━┻━━━━━━━━┻━━━━━━━━┻━━━━━━━━━━━━━━━━┻━━━━━━━━━━━━━━┛";
+        if any_interpolated {
+            final_string += "\n\u{001b}[33mYellow\u{001b}[0m dates were estimated because Economist Bot was offline";
+        }
+        final_string += "```";
 
         Ok(CommandResponseObject::interactive(CreateComponents::default(), final_string, true))
     }
@@ -0,0 +1,95 @@
+use crate::auth;
+use crate::commands::manage::*;
+use crate::commands::query::*;
+use crate::types::*;
+use crate::utils;
+use crate::CommandResponseObject;
+use async_trait::async_trait;
+use serenity::builder::CreateApplicationCommandOption;
+use serenity::model::channel::ChannelType;
+use serenity::model::prelude::command::CommandOptionType;
+use serenity::model::prelude::interaction::application_command::{
+    ApplicationCommandInteraction, CommandDataOption, CommandDataOptionValue,
+};
+
+pub struct ReportHandler {}
+
+#[async_trait]
+impl ApplicationCommandHandler for ReportHandler {
+    async fn handle_application_command(&mut self, data: &ApplicationCommandInteraction, query_agent: &DBQueryAgent, manager: &DBManager) -> Result<CommandResponseObject, String> {
+        let options = match utils::get_options(data) {
+            Ok(o) => o,
+            Err(e) => return Err(format!("Error while getting options from command data: {e:?}"))
+        };
+
+        // Without any options this just shows the current channel, which anyone can do
+        if options.is_empty() {
+            let settings = match query_agent.get_guild_settings().await {
+                Ok(s) => s,
+                Err(e) => return Err(format!("Error getting the report channel: {e:?}"))
+            };
+            return Ok(CommandResponseObject::text(Self::describe_settings(&settings)))
+        }
+
+        auth::require_guild_admin(data)?;
+        let report_channel_id = Self::parse_options(&options)?;
+
+        let settings = match manager.set_report_channel(report_channel_id, &data.user).await {
+            Ok(s) => s,
+            Err(e) => return Err(format!("Error saving the report channel: {e:?}"))
+        };
+
+        Ok(CommandResponseObject::text(format!("{} changed the market report channel\n{}", data.user, Self::describe_settings(&settings))))
+    }
+
+    fn get_name(&self) -> &str { "report" }
+    fn get_description(&self) -> &str { "View or change the channel the daily market report is posted to at closing" }
+    fn get_option_kind(&self) -> CommandOptionType { CommandOptionType::SubCommand }
+    fn register(&self) -> Vec<CreateApplicationCommandOption> {
+        vec![
+            CreateApplicationCommandOption::default()
+                .kind(CommandOptionType::Channel)
+                .name("channel")
+                .description("Channel to post the market report in")
+                .channel_types(&[ChannelType::Text])
+                .clone(),
+            CreateApplicationCommandOption::default()
+                .kind(CommandOptionType::Boolean)
+                .name("clear")
+                .description("Post the market report to the ledger channel instead")
+                .clone()
+        ]
+    }
+}
+
+impl ReportHandler {
+    pub fn new() -> Self {
+        ReportHandler {}
+    }
+
+    fn parse_options(options: &[CommandDataOption]) -> Result<Option<i64>, String> {
+        let mut channel = None;
+        let mut clear = false;
+        for option in options {
+            match (option.name.as_str(), option.resolved.clone()) {
+                ("channel", Some(CommandDataOptionValue::Channel(c))) => channel = Some(c.id.0 as i64),
+                ("clear", Some(CommandDataOptionValue::Boolean(c))) => clear = c,
+                _ => {}
+            }
+        }
+
+        match (channel, clear) {
+            (Some(_), true) => Err("Error: choose either a new channel or `clear`, not both".into()),
+            (None, false) => Err("Error: choose a channel, or `clear` to post the report to the ledger channel".into()),
+            (channel, _) => Ok(channel)
+        }
+    }
+
+    fn describe_settings(settings: &GuildSettings) -> String {
+        match (settings.report_channel_id, settings.ledger_channel_id) {
+            (Some(channel_id), _) => format!("> Market report channel: <#{channel_id}>"),
+            (None, Some(channel_id)) => format!("> Market report channel: _none, the report is posted to the ledger channel <#{channel_id}>_"),
+            (None, None) => "> Market report channel: _none, the report isn't posted_".into()
+        }
+    }
+}
@@ -1,4 +1,6 @@
+use crate::auth;
 use crate::types::*;
+use crate::ledger;
 use crate::CommandResponseObject;
 use async_trait::async_trait;
 use serenity::model::prelude::interaction::application_command::{
@@ -8,22 +10,20 @@ use serenity::model::prelude::interaction::application_command::{
 use serenity::model::application::interaction::message_component::MessageComponentInteraction;
 use serenity::model::application::component::ButtonStyle;
 use serenity::model::prelude::command::CommandOptionType;
-use serenity::model::user::User;
 use serenity::builder::{CreateComponents, CreateApplicationCommandOption};
 use crate::commands::query::*;
 use crate::commands::manage::*;
 use crate::utils;
+use crate::utils::pending::PendingActions;
 use tracing::info;
 
 pub struct ReserveHandler {
-    transaction_code: String,
-    transaction_amount: i64,
-    transaction_initiator: User
+    pending: PendingActions<PendingTransaction>
 }
 
 #[async_trait]
 impl ApplicationCommandHandler for ReserveHandler {
-    async fn handle_application_command(&mut self, data: &ApplicationCommandInteraction, query_agent: &DBQueryAgent, _manager: &DBManager) -> Result<CommandResponseObject, String> {
+    async fn handle_application_command(&mut self, data: &ApplicationCommandInteraction, query_agent: &DBQueryAgent, manager: &DBManager) -> Result<CommandResponseObject, String> {
         info!("Handling command from `{}`", self.get_name());
         let cmd = match data.data.options.get(0) {
             Some(a) => a,
@@ -57,18 +57,14 @@ impl ApplicationCommandHandler for ReserveHandler {
             }
         };
 
-        self.transaction_code = currency_code.clone();
-        self.transaction_amount = amount;
-        self.transaction_initiator = data.user.clone();
+        let currency_data = auth::authorize(query_agent, manager, currency_code.clone(), &data.user, CurrencyPermission::Reserve).await?;
 
-        match query_agent.get_currency_data(currency_code.clone()).await {
-            Ok(currency_data) => if data.user.name == currency_data.owner {
-                Ok(self.generate_command_response(currency_data, amount))
-            } else {
-                Err("Error: you are not the owner of this currency, and therefore cannot modify it".into())
-            },
-            Err(e) => Err(format!("An error occured while performing a database lookup: {e:?}"))
-        }
+        let token = self.pending.insert(PendingTransaction {
+            currency_code,
+            amount,
+            initiator: data.user.clone()
+        });
+        Ok(self.generate_command_response(currency_data, amount, &token))
     }
     fn get_name(&self) -> &str { "reserve" }
     fn get_description(&self) -> &str { "Manage gold reserves of a currency" }
@@ -120,24 +116,26 @@ impl ApplicationCommandHandler for ReserveHandler {
 
 #[async_trait]
 impl InteractionResponseHandler for ReserveHandler {
-    async fn handle_interaction_response(&self, data: &MessageComponentInteraction, query_agent: &DBQueryAgent, manager: &DBManager) -> Result<CommandResponseObject, String> {
-        info!("Transaction details: code: `{}`, amount: `{}`, initiator: `{}`", self.transaction_code.clone(), self.transaction_amount, self.transaction_initiator.name.clone());
-        match data.data.custom_id.as_str() {
+    async fn handle_interaction_response(&mut self, data: &MessageComponentInteraction, _query_agent: &DBQueryAgent, manager: &DBManager) -> Result<CommandResponseObject, String> {
+        let (callsign, token) = utils::split_custom_id(data.data.custom_id.as_str());
+        let pending = match token.and_then(|token| self.pending.take(token)) {
+            Some(p) => p,
+            None => return Err("This transaction has expired or has already been completed. Please run the command again.".into())
+        };
+
+        info!("Transaction details: code: `{}`, amount: `{}`, initiator: `{}`", pending.currency_code.clone(), pending.amount, pending.initiator.name.clone());
+        match callsign {
             "reserve-transaction-confirm" => {
-                let transaction_response = match manager.reserve_modify(self.transaction_code.clone(), self.transaction_amount, self.transaction_initiator.name.clone()).await {
+                let (transaction_response, currency_data) = match manager.reserve_modify(pending.currency_code.clone(), pending.amount, &pending.initiator).await {
                     Ok(data) => data,
                     Err(e) => return Err(format!("Error while completing reserve transaction: `{e:?}`"))
                 };
 
-                let currency_data = match query_agent.get_currency_data(self.transaction_code.clone()).await {
-                            Ok(data) => data,
-                            Err(e) => return Ok(CommandResponseObject::interactive_with_feedback(CreateComponents::default(), format!("Error while completing currency reserve check: `{e:?}`"), "", true))
-                        };
-
                 let feedback = format!("Successfully completed gold reserve transaction!");
-                let broadcast = format!("{0} made a gold reserve transaction:\n> Currency: **{1}** `{2}`\n> Nation/State: *{6}*\n> Amount: `{3} ingots`\n> New balance: `{4} ingots`\n> Transaction ID: `#{5:0>5}`", data.user, currency_data.currency_name, self.transaction_code, self.transaction_amount, currency_data.reserves, transaction_response.transaction_id, currency_data.state);
+                let broadcast = format!("{0} made a gold reserve transaction:\n> Currency: **{1}** `{2}`\n> Nation/State: *{6}*\n> Amount: `{3} ingots`\n> New balance: `{4} ingots`\n> Transaction ID: `#{5:0>5}`", data.user, currency_data.currency_name, pending.currency_code, pending.amount, currency_data.reserves, transaction_response.transaction_id, currency_data.state);
 
-                Ok(CommandResponseObject::interactive_with_feedback(CreateComponents::default(), feedback, broadcast, true))
+                Ok(CommandResponseObject::interactive_with_feedback(CreateComponents::default(), feedback, broadcast, true)
+                    .with_ledger(ledger::transaction(&transaction_response, &currency_data)))
             },
             "reserve-transaction-cancel" => {
                 Ok(CommandResponseObject::interactive_with_feedback(CreateComponents::default(), "Cancelled transaction. No records were updated.", "", true))
@@ -154,9 +152,7 @@ impl InteractionResponseHandler for ReserveHandler {
 impl ReserveHandler {
     pub fn new() -> Self {
         ReserveHandler {
-            transaction_code: String::new(),
-            transaction_amount: 0,
-            transaction_initiator: User::default()
+            pending: PendingActions::default()
         }
     }
 
@@ -194,7 +190,7 @@ impl ReserveHandler {
         Ok((amount, currency_code))
     }
 
-    fn generate_command_response(&self, data: CurrencyData, amount: i64) -> CommandResponseObject {
+    fn generate_command_response(&self, data: CurrencyData, amount: i64, token: &str) -> CommandResponseObject {
         let new_reserves = data.reserves + amount;
         
         let components = CreateComponents::default()
@@ -204,13 +200,13 @@ impl ReserveHandler {
                         button
                             .label("Confirm")
                             .style(ButtonStyle::Primary)
-                            .custom_id("reserve-transaction-confirm")
+                            .custom_id(utils::custom_id("reserve-transaction-confirm", token))
                     })
                     .create_button(|button| {
                         button
                             .label("Cancel")
                             .style(ButtonStyle::Secondary)
-                            .custom_id("reserve-transaction-cancel")
+                            .custom_id(utils::custom_id("reserve-transaction-cancel", token))
                     })
             }).clone();
 
@@ -0,0 +1,78 @@
+use crate::auth;
+use crate::commands::manage::*;
+use crate::commands::query::*;
+use crate::types::*;
+use crate::ledger::{self, CurrencyEvent};
+use crate::utils;
+use crate::CommandResponseObject;
+use async_trait::async_trait;
+use serenity::builder::CreateApplicationCommandOption;
+use serenity::model::prelude::command::CommandOptionType;
+use serenity::model::prelude::interaction::application_command::{
+    ApplicationCommandInteraction, CommandDataOption, CommandDataOptionValue,
+};
+
+pub struct RestoreHandler {}
+
+#[async_trait]
+impl ApplicationCommandHandler for RestoreHandler {
+    async fn handle_application_command(&mut self, data: &ApplicationCommandInteraction, query_agent: &DBQueryAgent, manager: &DBManager) -> Result<CommandResponseObject, String> {
+        let options = match utils::get_options(data) {
+            Ok(o) => o,
+            Err(e) => return Err(format!("Error while getting options from command data: {e:?}"))
+        };
+
+        let currency_code = self.parse_options(&options)?;
+
+        let archived = match query_agent.get_archived_currency_data(currency_code.clone()).await {
+            Ok(c) => c,
+            Err(_e) => return Err(format!("Error: could not find an archived currency with the code `{currency_code}`"))
+        };
+
+        // Restoring undoes a deletion, so it needs the same permission
+        auth::check_permission(query_agent, manager, &archived, &data.user, CurrencyPermission::Delete).await?;
+
+        let currency_data = match manager.restore_currency(archived.currency_id, &data.user).await {
+            Ok(c) => c,
+            Err(e) => return Err(format!("Error restoring currency from the archive: {e:?}"))
+        };
+
+        Ok(CommandResponseObject::text(
+            format!("{} restored currency **{}** `{}` from the archive", data.user, currency_data.currency_name, currency_data.currency_code)
+        ).with_ledger(ledger::currency_event(CurrencyEvent::Restored, &currency_data, &data.user)))
+    }
+
+    fn get_name(&self) -> &str { "restore" }
+    fn get_description(&self) -> &str { "Restore a deleted currency from the archive" }
+    fn get_option_kind(&self) -> CommandOptionType { CommandOptionType::SubCommand }
+    fn register(&self) -> Vec<CreateApplicationCommandOption> {
+        vec![
+            CreateApplicationCommandOption::default()
+                .kind(CommandOptionType::String)
+                .name("code")
+                .description("The three-letter code of the deleted currency")
+                .min_length(3)
+                .max_length(3)
+                .required(true)
+                .clone()
+        ]
+    }
+}
+
+impl RestoreHandler {
+    pub fn new() -> Self {
+        RestoreHandler {}
+    }
+
+    fn parse_options(&self, options: &[CommandDataOption]) -> Result<String, String> {
+        for option in options {
+            if option.name.as_str() == "code" {
+                if let Some(CommandDataOptionValue::String(code)) = option.resolved.clone() {
+                    return Ok(code)
+                }
+            }
+        }
+
+        Err("Error: no currency code specified".into())
+    }
+}
@@ -0,0 +1,174 @@
+use crate::auth;
+use crate::commands::manage::*;
+use crate::commands::query::*;
+use crate::types::*;
+use crate::ledger;
+use crate::utils;
+use crate::utils::pending::PendingActions;
+use crate::CommandResponseObject;
+use async_trait::async_trait;
+use serenity::model::application::interaction::message_component::MessageComponentInteraction;
+use serenity::model::application::component::ButtonStyle;
+use serenity::builder::{CreateComponents, CreateApplicationCommandOption};
+use serenity::model::prelude::command::CommandOptionType;
+use serenity::model::prelude::interaction::application_command::{
+    ApplicationCommandInteraction, CommandDataOption, CommandDataOptionValue,
+};
+use tracing::info;
+
+pub struct RevertHandler {
+    pending: PendingActions<TransactionData>
+}
+
+#[async_trait]
+impl ApplicationCommandHandler for RevertHandler {
+    async fn handle_application_command(&mut self, data: &ApplicationCommandInteraction, query_agent: &DBQueryAgent, manager: &DBManager) -> Result<CommandResponseObject, String> {
+        info!("Handling command from `{}`", self.get_name());
+        let options = match utils::get_options(data) {
+            Ok(o) => o,
+            Err(e) => return Err(format!("Error while parsing options: {e:?}"))
+        };
+
+        let transaction_id = match self.parse_options(&options) {
+            Ok(id) => id,
+            Err(e) => return Err(format!("Error while parsing options: {e}"))
+        };
+
+        let transaction = match query_agent.get_transaction_data(transaction_id).await {
+            Ok(t) => t,
+            Err(_e) => return Err(format!("Error: could not find transaction `#{transaction_id:0>5}`"))
+        };
+
+        if let Some(reverted_id) = transaction.reverts_transaction_id {
+            return Err(format!("Error: transaction `#{transaction_id:0>5}` is a reversal of `#{reverted_id:0>5}` and can't be reverted itself"))
+        }
+        if let Some(reversal_id) = transaction.reverted_by {
+            return Err(format!("Error: transaction `#{transaction_id:0>5}` has already been reverted by `#{reversal_id:0>5}`"))
+        }
+
+        let currency_data = auth::authorize(query_agent, manager, transaction.currency_code.clone(), &data.user, CurrencyPermission::Owner).await?;
+
+        let token = self.pending.insert(transaction.clone());
+
+        let components = CreateComponents::default()
+            .create_action_row(|action_row| {
+                action_row
+                    .create_button(|button| {
+                        button
+                            .label("Confirm")
+                            .style(ButtonStyle::Danger)
+                            .custom_id(utils::custom_id("revert-transaction-confirm", &token))
+                    })
+                    .create_button(|button| {
+                        button
+                            .label("Cancel")
+                            .style(ButtonStyle::Primary)
+                            .custom_id(utils::custom_id("revert-transaction-cancel", &token))
+                    })
+            }).clone();
+
+        Ok(CommandResponseObject::interactive(
+            components,
+            format!("**Review transaction reversal**\n> Currency: **{0}** `{1}`\n> Nation/State: *{2}*\n> Transaction: `#{3:0>5}` by {4} on {5}\n> Reversal: {6}",
+                currency_data.currency_name,
+                currency_data.currency_code,
+                currency_data.state,
+                transaction.transaction_id,
+                transaction.initiator,
+                transaction.transaction_date.format("%Y-%m-%d %H:%M"),
+                Self::describe_reversal(&transaction, &currency_data.currency_code)
+            ),
+            true
+        ))
+    }
+
+    fn get_name(&self) -> &str { "transaction" }
+    fn get_description(&self) -> &str { "Manage individual currency transactions" }
+    fn register(&self) -> Vec<CreateApplicationCommandOption> {
+        vec![
+            CreateApplicationCommandOption::default()
+                .kind(CommandOptionType::SubCommand)
+                .name("revert")
+                .description("Undo a transaction by recording an opposite, linked transaction")
+                .create_sub_option(|option| {
+                    option
+                        .kind(CommandOptionType::Integer)
+                        .name("id")
+                        .description("The ID of the transaction to revert")
+                        .min_int_value(1)
+                        .required(true)
+                }).clone()
+        ]
+    }
+}
+
+#[async_trait]
+impl InteractionResponseHandler for RevertHandler {
+    async fn handle_interaction_response(&mut self, data: &MessageComponentInteraction, _query_agent: &DBQueryAgent, manager: &DBManager) -> Result<CommandResponseObject, String> {
+        let (callsign, token) = utils::split_custom_id(data.data.custom_id.as_str());
+        let transaction = match token.and_then(|token| self.pending.take(token)) {
+            Some(t) => t,
+            None => return Err("This reversal has expired or has already been completed. Please run the command again.".into())
+        };
+
+        match callsign {
+            "revert-transaction-confirm" => {
+                let (reversal, currency_data) = match manager.revert_transaction(transaction.transaction_id, &data.user).await {
+                    Ok(data) => data,
+                    Err(RevertError::IsReversal(reverted_id)) => return Err(format!("Error: transaction `#{:0>5}` is a reversal of `#{reverted_id:0>5}` and can't be reverted itself", transaction.transaction_id)),
+                    Err(RevertError::AlreadyReverted(reversal_id)) => return Err(format!("Error: transaction `#{:0>5}` has already been reverted by `#{reversal_id:0>5}`", transaction.transaction_id)),
+                    Err(RevertError::Db(e)) => return Err(format!("Error while reverting transaction: `{e:?}`"))
+                };
+
+                let feedback = format!("Successfully reverted transaction `#{:0>5}`!", transaction.transaction_id);
+                let broadcast = format!("{0} reverted a transaction:\n> Currency: **{1}** `{2}`\n> Nation/State: *{3}*\n> Reverted transaction: `#{4:0>5}`\n> Reversal: {5}\n> New balance: `{6} ingots`, `{7}{2}`\n> Transaction ID: `#{8:0>5}`",
+                    data.user,
+                    currency_data.currency_name,
+                    currency_data.currency_code,
+                    currency_data.state,
+                    transaction.transaction_id,
+                    Self::describe_reversal(&transaction, &currency_data.currency_code),
+                    currency_data.reserves,
+                    currency_data.circulation,
+                    reversal.transaction_id
+                );
+
+                Ok(CommandResponseObject::interactive_with_feedback(CreateComponents::default(), feedback, broadcast, true)
+                    .with_ledger(ledger::transaction(&reversal, &currency_data)))
+            },
+            _ => Ok(CommandResponseObject::interactive_with_feedback(CreateComponents::default(), "Cancelled reversal. No records were updated.", "", true))
+        }
+    }
+
+    fn get_pattern(&self) -> Vec<&str> {
+        vec!["revert-transaction-confirm", "revert-transaction-cancel"]
+    }
+}
+
+impl RevertHandler {
+    pub fn new() -> Self {
+        RevertHandler {
+            pending: PendingActions::default()
+        }
+    }
+
+    fn parse_options(&self, options: &Vec<CommandDataOption>) -> Result<i64, String> {
+        for option in options {
+            if option.name.as_str() == "id" {
+                if let Some(CommandDataOptionValue::Integer(id)) = option.resolved {
+                    return Ok(id)
+                }
+            }
+        }
+
+        Err("Error: no transaction ID specified".into())
+    }
+
+    fn describe_reversal(transaction: &TransactionData, currency_code: &str) -> String {
+        match (transaction.delta_reserves, transaction.delta_circulation) {
+            (Some(amount), _) => format!("`{} ingots` of gold reserves", -amount),
+            (None, Some(amount)) => format!("`{}{currency_code}` of circulation", -amount),
+            (None, None) => "nothing".into()
+        }
+    }
+}
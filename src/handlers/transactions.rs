@@ -0,0 +1,242 @@
+use crate::commands::manage::*;
+use crate::commands::query::*;
+use crate::types::*;
+use crate::utils;
+use crate::utils::pending::PendingActions;
+use crate::CommandResponseObject;
+use async_trait::async_trait;
+use chrono::NaiveDate;
+use serenity::model::application::interaction::message_component::MessageComponentInteraction;
+use serenity::model::application::component::ButtonStyle;
+use serenity::builder::{CreateComponents, CreateApplicationCommandOption};
+use serenity::model::prelude::command::CommandOptionType;
+use serenity::model::prelude::interaction::application_command::{
+    ApplicationCommandInteraction, CommandDataOption, CommandDataOptionValue,
+};
+
+const PAGE_SIZE: i64 = 10;
+
+pub struct TransactionsHandler {
+    pending: PendingActions<(TransactionFilter, i64)>
+}
+
+#[async_trait]
+impl ApplicationCommandHandler for TransactionsHandler {
+    async fn handle_application_command(&mut self, data: &ApplicationCommandInteraction, query_agent: &DBQueryAgent, _manager: &DBManager) -> Result<CommandResponseObject, String> {
+        let options = match utils::get_options(data) {
+            Ok(o) => o,
+            Err(e) => return Err(format!("Error while getting options from command data: {e:?}"))
+        };
+
+        let filter = match self.parse_options(&options) {
+            Ok(f) => f,
+            Err(e) => return Err(format!("Error while parsing options: {e}"))
+        };
+
+        let (components, page) = self.generate_page(query_agent, filter, 0).await?;
+
+        Ok(CommandResponseObject::interactive(components, page, true))
+    }
+
+    fn get_name(&self) -> &str { "transactions" }
+    fn get_description(&self) -> &str { "List past transactions for a currency" }
+    fn get_option_kind(&self) -> CommandOptionType { CommandOptionType::SubCommand }
+    fn register(&self) -> Vec<CreateApplicationCommandOption> {
+        vec![
+            CreateApplicationCommandOption::default()
+                .kind(CommandOptionType::String)
+                .name("code")
+                .description("Three-letter currency code to list transactions for")
+                .min_length(3)
+                .max_length(3)
+                .required(true)
+                .clone(),
+            CreateApplicationCommandOption::default()
+                .kind(CommandOptionType::String)
+                .name("kind")
+                .description("Only list one kind of transaction")
+                .add_string_choice("Gold Reserves", "reserve")
+                .add_string_choice("Circulation", "circulation")
+                .clone(),
+            CreateApplicationCommandOption::default()
+                .kind(CommandOptionType::User)
+                .name("initiator")
+                .description("Only list transactions made by this user")
+                .clone(),
+            CreateApplicationCommandOption::default()
+                .kind(CommandOptionType::String)
+                .name("from")
+                .description("Only list transactions on or after this date (YYYY-MM-DD)")
+                .min_length(10)
+                .max_length(10)
+                .clone(),
+            CreateApplicationCommandOption::default()
+                .kind(CommandOptionType::String)
+                .name("to")
+                .description("Only list transactions on or before this date (YYYY-MM-DD)")
+                .min_length(10)
+                .max_length(10)
+                .clone()
+        ]
+    }
+}
+
+#[async_trait]
+impl InteractionResponseHandler for TransactionsHandler {
+    async fn handle_interaction_response(&mut self, data: &MessageComponentInteraction, query_agent: &DBQueryAgent, _manager: &DBManager) -> Result<CommandResponseObject, String> {
+        let (callsign, token) = utils::split_custom_id(data.data.custom_id.as_str());
+        let (filter, page) = match token.and_then(|token| self.pending.take(token)) {
+            Some(p) => p,
+            None => return Err("This transaction list has expired. Please run the command again.".into())
+        };
+
+        let page = match callsign {
+            "transactions-next" => page + 1,
+            "transactions-previous" => (page - 1).max(0),
+            _ => page
+        };
+
+        let (components, page) = self.generate_page(query_agent, filter, page).await?;
+
+        Ok(CommandResponseObject::interactive_with_feedback(components, page, "", true))
+    }
+
+    fn get_pattern(&self) -> Vec<&str> {
+        vec!["transactions-next", "transactions-previous"]
+    }
+}
+
+impl TransactionsHandler {
+    pub fn new() -> Self {
+        TransactionsHandler {
+            pending: PendingActions::default()
+        }
+    }
+
+    fn parse_options(&self, options: &Vec<CommandDataOption>) -> Result<TransactionFilter, String> {
+        let mut filter = TransactionFilter::default();
+
+        for option in options {
+            match option.name.as_str() {
+                "code" => if let Some(CommandDataOptionValue::String(code)) = option.resolved.clone() {
+                    filter.currency_code = code;
+                },
+                "kind" => if let Some(CommandDataOptionValue::String(kind)) = option.resolved.clone() {
+                    filter.kind = match kind.as_str() {
+                        "reserve" => Some(TransactionKind::Reserve),
+                        "circulation" => Some(TransactionKind::Circulation),
+                        _ => None
+                    };
+                },
+                "initiator" => if let Some(CommandDataOptionValue::User(user, _)) = option.resolved.clone() {
+                    filter.initiator_id = Some(user.id.0 as i64);
+                },
+                "from" => if let Some(CommandDataOptionValue::String(date)) = option.resolved.clone() {
+                    filter.from_date = Some(Self::parse_date(&date)?);
+                },
+                "to" => if let Some(CommandDataOptionValue::String(date)) = option.resolved.clone() {
+                    filter.to_date = Some(Self::parse_date(&date)?);
+                },
+                _ => {}
+            }
+        }
+
+        if filter.currency_code.is_empty() { return Err("Error: no currency code specified".into()) }
+
+        Ok(filter)
+    }
+
+    fn parse_date(date: &str) -> Result<NaiveDate, String> {
+        match NaiveDate::parse_from_str(date, "%Y-%m-%d") {
+            Ok(d) => Ok(d),
+            Err(_) => Err(format!("`{date}` is not a valid date, please use the format YYYY-MM-DD"))
+        }
+    }
+
+    async fn generate_page(&mut self, query_agent: &DBQueryAgent, filter: TransactionFilter, page: i64) -> Result<(CreateComponents, String), String> {
+        let currency = match query_agent.get_currency_data(filter.currency_code.clone()).await {
+            Ok(c) => c,
+            Err(e) => return Err(format!("Error while getting currency data: {e:?}"))
+        };
+
+        let total = match query_agent.count_transactions(&filter).await {
+            Ok(t) => t,
+            Err(e) => return Err(format!("Error while counting transactions: {e:?}"))
+        };
+
+        let page_count = ((total + PAGE_SIZE - 1) / PAGE_SIZE).max(1);
+        let page = page.min(page_count - 1);
+
+        let transactions = match query_agent.list_transactions(&filter, PAGE_SIZE, page * PAGE_SIZE).await {
+            Ok(t) => t,
+            Err(e) => return Err(format!("Error while looking up transactions: {e:?}"))
+        };
+
+        let currency_string = format!("[\u{001b}[36m{0}\u{001b}[0m] \u{001b}[1m{1}\u{001b}[0m", currency.currency_code, currency.currency_name);
+
+        let mut final_string = format!("```ansi\nTransaction list for {currency_string} (page {0} of {page_count}, {total} total)\n", page + 1);
+        final_string += "┏━━━━━━━┳━━━━━━━━━━━━━━━━┳━━━━━━━━━━━━━┳━━━━━━━━━━━━━━━━━━┳━━━━━━━━━━━━━━━━━━━━┳━━━━━━━━━━━━━━━┓\n";
+        final_string += "┃ID     ┃Date            ┃Type         ┃Amount            ┃Initiator           ┃Status         ┃\n";
+        final_string += "┣━━━━━━━╋━━━━━━━━━━━━━━━━╋━━━━━━━━━━━━━╋━━━━━━━━━━━━━━━━━━╋━━━━━━━━━━━━━━━━━━━━╋━━━━━━━━━━━━━━━┫";
+
+        for transaction in transactions {
+            let (kind, amount, unit) = match (transaction.delta_reserves, transaction.delta_circulation) {
+                (Some(amount), _) => ("Gold Reserve", amount, "ingots".to_string()),
+                (None, Some(amount)) => ("Circulation", amount, currency.currency_code.clone()),
+                (None, None) => ("Unknown", 0, String::new())
+            };
+            let amount_color = if amount < 0 {
+                    "\u{001b}[1;31m"
+                } else {
+                    "\u{001b}[1;32m"
+                };
+            let status = match (transaction.reverts_transaction_id, transaction.reverted_by) {
+                (Some(id), _) => format!("\u{001b}[1;33mReversal #{id:0>5}\u{001b}[0m"),
+                (None, Some(_)) => "\u{001b}[1;33mReverted       \u{001b}[0m".to_string(),
+                (None, None) => " ".repeat(15)
+            };
+            final_string += format!(
+                "\n┃#{0:0>5} ┃{1: <16.16}┃{2: <13.13}┃{amount_color}{3: >+10}\u{001b}[0m {4: <7.7}┃{5: <20.20}┃{status}┃",
+                transaction.transaction_id,
+                transaction.transaction_date.format("%Y-%m-%d %H:%M").to_string(),
+                kind,
+                amount,
+                unit,
+                transaction.initiator
+            ).as_str()
+        }
+        // ID: 7 chars (#00000 and padding)
+        // Date: 16 chars
+        // Type: 'Gold Reserve' 'Circulation' 13 chars
+        // Amount: 10 chars + 7 char unit
+        // Initiator: 20 chars
+        // Status: 'Reverted' 'Reversal #00000' 15 chars
+
+        final_string += "\n┗━━━━━━━┻━━━━━━━━━━━━━━━━┻━━━━━━━━━━━━━┻━━━━━━━━━━━━━━━━━━┻━━━━━━━━━━━━━━━━━━━━┻━━━━━━━━━━━━━━━┛```";
+
+        let first_page = page == 0;
+        let last_page = page >= page_count - 1;
+        let token = self.pending.insert((filter, page));
+
+        let components = CreateComponents::default()
+            .create_action_row(|action_row| {
+                action_row
+                    .create_button(|button| {
+                        button
+                            .label("Previous")
+                            .style(ButtonStyle::Secondary)
+                            .custom_id(utils::custom_id("transactions-previous", &token))
+                            .disabled(first_page)
+                    })
+                    .create_button(|button| {
+                        button
+                            .label("Next")
+                            .style(ButtonStyle::Primary)
+                            .custom_id(utils::custom_id("transactions-next", &token))
+                            .disabled(last_page)
+                    })
+            }).clone();
+
+        Ok((components, final_string))
+    }
+}
@@ -2,9 +2,12 @@ use crate::commands::manage::*;
 use crate::commands::query::*;
 use crate::types::*;
 use crate::utils;
+use crate::charts::{ChartKind, ChartOverlays, ChartTheme, CurrencyChart};
 use crate::CommandResponseObject;
 use async_trait::async_trait;
-use tracing::info;
+use tracing::{error, info};
+use tokio::task;
+use chrono::NaiveDate;
 use serenity::builder::CreateApplicationCommandOption;
 use serenity::model::prelude::command::CommandOptionType;
 use serenity::model::prelude::interaction::application_command::{
@@ -13,6 +16,14 @@ use serenity::model::prelude::interaction::application_command::{
 
 pub struct ViewHandler {}
 
+struct ViewOptions {
+    code: String,
+    since: Option<NaiveDate>, // None for all records
+    kind: ChartKind,
+    overlays: ChartOverlays,
+    theme: ChartTheme
+}
+
 #[async_trait]
 impl ApplicationCommandHandler for ViewHandler {
     async fn handle_application_command(&mut self, data: &ApplicationCommandInteraction, query_agent: &DBQueryAgent, _manager: &DBManager) -> Result<CommandResponseObject, String> {
@@ -22,17 +33,17 @@ impl ApplicationCommandHandler for ViewHandler {
             Err(e) => return Err(format!("Error while parsing options: {e:?}"))
         };
 
-        let code = match self.parse_options(&options) {
-            Ok(c) => c,
+        let view_options = match self.parse_options(&options) {
+            Ok(o) => o,
             Err(e) => return Err(e)
         };
 
-        let currency_data = match query_agent.get_currency_data(code.clone()).await {
+        let currency_data = match query_agent.get_currency_data(view_options.code.clone()).await {
             Ok(d) => d,
             Err(e) => return Err(format!("Error getting currency data: {e:?}"))
         };
 
-        let records = match query_agent.get_reports(1, code).await {
+        let records = match query_agent.get_reports(None, view_options.code.clone(), view_options.since).await {
             Ok(r) => r,
             Err(e) => return Err(format!("Error getting records: {e:?}"))
         };
@@ -50,29 +61,45 @@ impl ApplicationCommandHandler for ViewHandler {
                 currency_data.value
             );
 
-        match records.get(0) {
-            Some(record) => {
-                let record_id = record.record_id;
-                info!("Using url https://economist-image-server.shuttleapp.rs/{:05}/{:05}", currency_data.currency_id, record_id);
-
-                embed = embed
-                    .image(format!("https://economist-image-server.shuttleapp.rs/{:05}/{:05}", currency_data.currency_id, record_id))
-                    .clone();
-            },
-            None => {
-                description += "\n```ansi\n\u{001b}[1;33mWarning:\u{001b}[0m No past records available for this currency```"
+        let mut chart = None;
+        if records.is_empty() {
+            description += "\n```ansi\n\u{001b}[1;33mWarning:\u{001b}[0m No past records available for this currency```"
+        } else {
+            let filename = format!("{}.png", currency_data.currency_code);
+            let currency = currency_data.clone();
+
+            // Rendering is CPU-bound, so keep it off the async runtime's worker threads
+            let (kind, overlays, theme) = (view_options.kind, view_options.overlays, view_options.theme);
+            let rendered = task::spawn_blocking(move || {
+                CurrencyChart::new(&currency, &records)
+                    .kind(kind)
+                    .overlays(overlays)
+                    .theme(theme)
+                    .render()
+            }).await;
+            match rendered.map_err(anyhow::Error::from).and_then(|result| result) {
+                Ok(png) => {
+                    embed = embed
+                        .image(format!("attachment://{filename}"))
+                        .clone();
+                    chart = Some((filename, png));
+                },
+                Err(e) => {
+                    error!("Couldn't render chart for `{}`: {e:?}", currency_data.currency_code);
+                    description += "\n```ansi\n\u{001b}[1;33mWarning:\u{001b}[0m Couldn't render the performance graph for this currency```"
+                }
             }
-        };
+        }
 
         embed = embed
             .description(description)
             .clone();
 
-        Ok(
-            CommandResponseObject::embed(
-                embed.clone()
-            )
-        )
+        let response = CommandResponseObject::embed(embed.clone());
+        Ok(match chart {
+            Some((filename, png)) => response.with_file(filename, png),
+            None => response
+        })
     }
 
     fn get_name(&self) -> &str { "view" }
@@ -85,7 +112,25 @@ impl ApplicationCommandHandler for ViewHandler {
                 .name("code")
                 .description("Three-letter currency code to view")
                 .required(true)
-                .clone()
+                .clone(),
+            utils::range_option(),
+            CreateApplicationCommandOption::default()
+                .kind(CommandOptionType::String)
+                .name("chart")
+                .description("Type of graph to draw (default: line)")
+                .add_string_choice("Line (closing value)", "line")
+                .add_string_choice("Candle (opening and closing value)", "candle")
+                .add_string_choice("Bar (daily change in value)", "bar")
+                .clone(),
+            CreateApplicationCommandOption::default()
+                .kind(CommandOptionType::String)
+                .name("overlay")
+                .description("Also plot circulation and/or gold reserves on a second axis")
+                .add_string_choice("Circulation", "circulation")
+                .add_string_choice("Gold Reserves", "reserves")
+                .add_string_choice("Circulation and Gold Reserves", "both")
+                .clone(),
+            utils::theme_option()
         ]
     }
 }
@@ -95,22 +140,38 @@ impl ViewHandler {
         ViewHandler {}
     }
 
-    fn parse_options(&self, options: &Vec<CommandDataOption>) -> Result<String, String> {
+    fn parse_options(&self, options: &[CommandDataOption]) -> Result<ViewOptions, String> {
         let mut code = None;
+        let mut view_options = ViewOptions {
+            code: String::new(),
+            since: utils::range_start("30d"),
+            kind: ChartKind::Line,
+            overlays: ChartOverlays::default(),
+            theme: ChartTheme::Dark
+        };
+
         for option in options {
-            match option.name.as_str() {
-                "code" => {
-                    info!("{:?}", option.resolved.clone());
-                    if let Some(CommandDataOptionValue::String(c_code)) = option.resolved.clone() {
-                        code = Some(c_code);
-                    }
-                }
+            let Some(CommandDataOptionValue::String(value)) = option.resolved.clone() else {
+                continue
+            };
+            match (option.name.as_str(), value.as_str()) {
+                ("code", _) => code = Some(value),
+                ("range", range) => view_options.since = utils::range_start(range),
+                ("chart", "line") => view_options.kind = ChartKind::Line,
+                ("chart", "candle") => view_options.kind = ChartKind::Candle,
+                ("chart", "bar") => view_options.kind = ChartKind::Bar,
+                ("theme", "dark") => view_options.theme = ChartTheme::Dark,
+                ("theme", "light") => view_options.theme = ChartTheme::Light,
+                ("overlay", overlay) => view_options.overlays = ChartOverlays {
+                    circulation: overlay == "circulation" || overlay == "both",
+                    reserves: overlay == "reserves" || overlay == "both"
+                },
                 _ => {}
             }
         }
 
         match code {
-            Some(code) => Ok(code),
+            Some(code) => Ok(ViewOptions { code, ..view_options }),
             None => Err("Couldn't get code from options".into())
         }
     }
@@ -0,0 +1,100 @@
+use crate::auth;
+use crate::commands::manage::*;
+use crate::commands::query::*;
+use crate::types::*;
+use crate::utils;
+use crate::workers::records::RecordWorkers;
+use crate::CommandResponseObject;
+use async_trait::async_trait;
+use chrono::NaiveDate;
+use serenity::builder::CreateApplicationCommandOption;
+use serenity::model::prelude::command::CommandOptionType;
+use serenity::model::prelude::interaction::application_command::{
+    ApplicationCommandInteraction, CommandDataOption, CommandDataOptionValue,
+};
+
+pub struct WorkerHandler {
+    record_workers: RecordWorkers
+}
+
+#[async_trait]
+impl ApplicationCommandHandler for WorkerHandler {
+    async fn handle_application_command(&mut self, data: &ApplicationCommandInteraction, query_agent: &DBQueryAgent, _manager: &DBManager) -> Result<CommandResponseObject, String> {
+        auth::require_guild_admin(data)?;
+
+        let options = match utils::get_options(data) {
+            Ok(o) => o,
+            Err(e) => return Err(format!("Error while getting options from command data: {e:?}"))
+        };
+
+        let action = match data.data.options.first().and_then(|command| command.options.first()) {
+            Some(a) => a,
+            None => return Err("Error while parsing options: Couldn't get subcommand data".into())
+        };
+
+        let (message, description) = match action.name.as_str() {
+            "open" => (WorkerMessage::ForceOpen, "open the market early".to_string()),
+            "close" => (WorkerMessage::ForceClose, "close the market early".to_string()),
+            "regenerate" => {
+                let record_date = Self::parse_date(&options)?;
+                (WorkerMessage::RebuildRecords(record_date), format!("rebuild the records for {record_date}"))
+            },
+            _ => return Err("Error: couldn't find the requested subcommand".into())
+        };
+
+        if let Err(e) = self.record_workers.send(query_agent.guild_id(), message) {
+            return Err(format!("Error: couldn't reach the record worker: {e}"))
+        }
+
+        Ok(CommandResponseObject::text(format!("{} asked the record worker to {description}", data.user)))
+    }
+
+    fn get_name(&self) -> &str { "worker" }
+    fn get_description(&self) -> &str { "Manually open or close the market, or redo a day's records" }
+    fn register(&self) -> Vec<CreateApplicationCommandOption> {
+        vec![
+            CreateApplicationCommandOption::default()
+                .kind(CommandOptionType::SubCommand)
+                .name("open")
+                .description("Open the market now, until the next scheduled close")
+                .clone(),
+            CreateApplicationCommandOption::default()
+                .kind(CommandOptionType::SubCommand)
+                .name("close")
+                .description("Close the market now and write today's records")
+                .clone(),
+            CreateApplicationCommandOption::default()
+                .kind(CommandOptionType::SubCommand)
+                .name("regenerate")
+                .description(format!("Rebuild a day's records from the snapshots taken that day, within the last {SNAPSHOT_RETENTION_SESSIONS} sessions"))
+                .create_sub_option(|option| {
+                    option
+                        .kind(CommandOptionType::String)
+                        .name("date")
+                        .description("Day to rebuild, as YYYY-MM-DD")
+                        .required(true)
+                })
+                .clone()
+        ]
+    }
+}
+
+impl WorkerHandler {
+    pub fn new(record_workers: RecordWorkers) -> Self {
+        WorkerHandler {
+            record_workers
+        }
+    }
+
+    fn parse_date(options: &[CommandDataOption]) -> Result<NaiveDate, String> {
+        let date = options.iter()
+            .find(|option| option.name.as_str() == "date")
+            .and_then(|option| option.resolved.clone());
+
+        match date {
+            Some(CommandDataOptionValue::String(date)) => NaiveDate::parse_from_str(date.trim(), "%Y-%m-%d")
+                .map_err(|_| format!("Error: `{date}` is not a valid date, use `YYYY-MM-DD`")),
+            _ => Err("Error: no date specified".into())
+        }
+    }
+}
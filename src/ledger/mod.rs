@@ -0,0 +1,223 @@
+use crate::commands::query::DBQueryAgent;
+use crate::types::*;
+use chrono::{NaiveDate, Utc};
+use serenity::builder::CreateEmbed;
+use serenity::http::Http;
+use serenity::model::channel::AttachmentType;
+use serenity::model::id::ChannelId;
+use serenity::model::user::User;
+use serenity::utils::Colour;
+use std::borrow::Cow;
+use tracing::error;
+
+// Discord rejects embeds with more fields than this
+const MAX_FIELDS: usize = 25;
+
+// How many currencies are listed as gainers and as losers in the market report
+const TOP_MOVERS: usize = 5;
+
+const REPORT_CHART_FILENAME: &str = "market-report.png";
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum CurrencyEvent {
+    Created,
+    Modified,
+    Deleted,
+    Restored,
+    CoOwnerSet,
+    CoOwnerRemoved,
+    Transferred
+}
+
+// Posts to the server's ledger channel, if it has one. A failed post is only logged, the change it describes has already been made
+pub async fn post(http: &Http, query_agent: &DBQueryAgent, embed: CreateEmbed) {
+    let Some(settings) = guild_settings(query_agent).await else {
+        return
+    };
+    if let Some(channel_id) = settings.ledger_channel_id {
+        send(http, query_agent, channel_id, embed, None).await;
+    }
+}
+
+// Posts the daily market report to the server's report channel, or its ledger channel if it hasn't picked one
+pub async fn post_report(http: &Http, query_agent: &DBQueryAgent, mut embed: CreateEmbed, chart: Option<Vec<u8>>) {
+    let Some(settings) = guild_settings(query_agent).await else {
+        return
+    };
+    let Some(channel_id) = settings.report_channel_id.or(settings.ledger_channel_id) else {
+        return
+    };
+
+    if chart.is_some() {
+        embed.image(format!("attachment://{REPORT_CHART_FILENAME}"));
+    }
+    send(http, query_agent, channel_id, embed, chart).await;
+}
+
+pub fn transaction(transaction: &TransactionData, currency: &CurrencyData) -> CreateEmbed {
+    let (kind, amount) = match (transaction.delta_reserves, transaction.delta_circulation) {
+        (Some(amount), _) => ("Gold reserves", format!("{amount:+} ingots")),
+        (None, Some(amount)) => ("Circulation", format!("{amount:+} {}", currency.currency_code)),
+        (None, None) => ("Unknown", "0".into())
+    };
+    let increase = transaction.delta_reserves.or(transaction.delta_circulation).unwrap_or(0) >= 0;
+
+    let mut embed = entry(format!("Transaction #{:0>5}", transaction.transaction_id), if increase { Colour::DARK_GREEN } else { Colour::DARK_RED });
+    if let Some(reverted_id) = transaction.reverts_transaction_id {
+        embed.description(format!("Reverts transaction `#{reverted_id:0>5}`"));
+    }
+    embed
+        .field("Currency", describe_currency(currency), true)
+        .field(kind, format!("`{amount}`"), true)
+        .field("Initiator", transaction.initiator.clone(), true)
+        .field("Reserves", format!("`{} ingots`", currency.reserves), true)
+        .field("Circulation", format!("`{} {}`", currency.circulation, currency.currency_code), true)
+        .field("Value", format!("`{:.3} ingot / {}`", currency.value, currency.currency_code), true)
+        .timestamp(transaction.transaction_date.to_rfc3339())
+        .clone()
+}
+
+pub fn currency_event(event: CurrencyEvent, currency: &CurrencyData, actor: &User) -> CreateEmbed {
+    let (title, colour) = match event {
+        CurrencyEvent::Created => ("Currency created", Colour::BLUE),
+        CurrencyEvent::Modified => ("Currency modified", Colour::GOLD),
+        CurrencyEvent::Deleted => ("Currency deleted", Colour::DARK_RED),
+        CurrencyEvent::Restored => ("Currency restored", Colour::BLUE),
+        CurrencyEvent::CoOwnerSet => ("Co-owner added", Colour::PURPLE),
+        CurrencyEvent::CoOwnerRemoved => ("Co-owner removed", Colour::PURPLE),
+        CurrencyEvent::Transferred => ("Ownership transferred", Colour::PURPLE)
+    };
+
+    entry(title, colour)
+        .description(format!("{} by {actor}", describe_currency(currency)))
+        .field("Nation/State", currency.state.clone(), true)
+        .field("Owner", currency.owner.clone(), true)
+        .field("Reserves", format!("`{} ingots`", currency.reserves), true)
+        .field("Circulation", format!("`{} {}`", currency.circulation, currency.currency_code), true)
+        .field("Value", format!("`{:.3} ingot / {}`", currency.value, currency.currency_code), true)
+        .clone()
+}
+
+// Each currency's movement over the day, biggest movers first
+pub fn daily_summary(record_date: NaiveDate, mut days: Vec<(CurrencyData, RecordData)>) -> CreateEmbed {
+    days.sort_by(|(_, a), (_, b)| b.delta_value.abs().total_cmp(&a.delta_value.abs()));
+
+    let mut embed = entry(format!("Market closed for {record_date}"), Colour::DARK_GREY);
+    if days.is_empty() {
+        embed.description("No currencies were traded");
+    } else if days.len() > MAX_FIELDS {
+        embed.description(format!("Showing the {MAX_FIELDS} biggest movers of {} currencies", days.len()));
+    }
+
+    for (currency, record) in days.iter().take(MAX_FIELDS) {
+        embed.field(
+            format!("{} {}", growth_marker(record.growth), currency.currency_code),
+            format!("`{:.3}` → `{:.3}`{}", record.opening_value, record.closing_value, percent_change(record)),
+            true
+        );
+    }
+
+    embed
+}
+
+// The "closing bell" digest: the day's biggest gainers and losers, and the totals across every currency that closed
+pub fn market_report(record_date: NaiveDate, mut days: Vec<(CurrencyData, RecordData)>) -> CreateEmbed {
+    days.sort_by(|(_, a), (_, b)| b.delta_value.total_cmp(&a.delta_value));
+
+    let mut embed = entry(format!("Closing bell for {record_date}"), Colour::DARK_GREY);
+    if days.is_empty() {
+        return embed.description("No currencies were traded").clone()
+    }
+
+    let count = |growth: i16| days.iter().filter(|(_, record)| record.growth == growth).count();
+    embed.description(format!("{} currencies closed: {} up, {} down, {} steady", days.len(), count(1), count(-1), count(0)));
+
+    let gainers: Vec<String> = days.iter()
+        .filter(|(_, record)| record.delta_value > 0.0)
+        .take(TOP_MOVERS)
+        .map(|(currency, record)| describe_move(currency, record))
+        .collect();
+    let losers: Vec<String> = days.iter().rev()
+        .filter(|(_, record)| record.delta_value < 0.0)
+        .take(TOP_MOVERS)
+        .map(|(currency, record)| describe_move(currency, record))
+        .collect();
+
+    // Records taken before balances were stored at closing fall back to the balances now
+    let reserves: i64 = days.iter().map(|(currency, record)| record.closing_reserves.unwrap_or(currency.reserves)).sum();
+    let circulation: i64 = days.iter().map(|(currency, record)| record.closing_circulation.unwrap_or(currency.circulation)).sum();
+
+    embed
+        .field("Top gainers", list_or_none(gainers), false)
+        .field("Top losers", list_or_none(losers), false)
+        .field("Gold in reserves", format!("`{reserves} ingots`"), true)
+        .field("Total circulation", format!("`{circulation} units`"), true)
+        .clone()
+}
+
+// Every ledger entry looks the same apart from its title, colour and fields
+fn entry(title: impl Into<String>, colour: Colour) -> CreateEmbed {
+    CreateEmbed::default()
+        .title(title.into())
+        .colour(colour)
+        .footer(|footer| footer.text("Economist Bot ledger"))
+        .timestamp(Utc::now().to_rfc3339())
+        .clone()
+}
+
+async fn guild_settings(query_agent: &DBQueryAgent) -> Option<GuildSettings> {
+    match query_agent.get_guild_settings().await {
+        Ok(s) => Some(s),
+        Err(e) => {
+            error!("Couldn't look up the ledger channels of guild {}: {e:?}", query_agent.guild_id());
+            None
+        }
+    }
+}
+
+async fn send(http: &Http, query_agent: &DBQueryAgent, channel_id: i64, embed: CreateEmbed, chart: Option<Vec<u8>>) {
+    let sent = ChannelId(channel_id as u64).send_message(http, |message| {
+        if let Some(chart) = chart {
+            message.add_file(AttachmentType::Bytes {
+                data: Cow::Owned(chart),
+                filename: REPORT_CHART_FILENAME.into()
+            });
+        }
+        message.set_embed(embed)
+    }).await;
+
+    if let Err(e) = sent {
+        error!("Couldn't post to channel {channel_id} of guild {}: {e:?}", query_agent.guild_id());
+    }
+}
+
+fn describe_move(currency: &CurrencyData, record: &RecordData) -> String {
+    format!("{} {} `{:.3}` → `{:.3}`{}", growth_marker(record.growth), describe_currency(currency), record.opening_value, record.closing_value, percent_change(record))
+}
+
+fn percent_change(record: &RecordData) -> String {
+    match record.opening_value {
+        opening if opening > 0.0 => format!(" ({:+.2}%)", record.delta_value / opening * 100.0),
+        _ => String::new()
+    }
+}
+
+fn list_or_none(lines: Vec<String>) -> String {
+    if lines.is_empty() {
+        "_None_".into()
+    } else {
+        lines.join("\n")
+    }
+}
+
+fn describe_currency(currency: &CurrencyData) -> String {
+    format!("**{}** `{}`", currency.currency_name, currency.currency_code)
+}
+
+fn growth_marker(growth: i16) -> &'static str {
+    match growth {
+        1 => "▲",
+        -1 => "▼",
+        _ => "■"
+    }
+}
@@ -20,28 +20,35 @@ use anyhow::anyhow;
 use tracing::{error, info, debug, warn};
 use serenity::prelude::*;
 use tokio::sync::Mutex;
-use std::fs::create_dir_all;
 use std::fmt::Display;
 use std::sync::Arc;
+use serenity::http::Http;
 use serenity::model::{
-    gateway::Ready
+    gateway::Ready,
+    id::GuildId
 };
 use serenity::async_trait;
-use serenity::builder::CreateApplicationCommandOption;
+use serenity::builder::{CreateApplicationCommandOption, CreateInteractionResponseData};
+use serenity::model::channel::AttachmentType;
+use std::borrow::Cow;
 use serenity::model::application::command::Command;
 use serenity::model::application::interaction::{Interaction, InteractionResponseType};
 use crate::commands::manage::DBManager;
 use crate::commands::query::DBQueryAgent;
 use crate::workers::records::*;
+use crate::workers::archive::*;
 use sqlx::{Connection, Row};
+use sqlx::migrate::Migrator;
 use shuttle_secrets::SecretStore;
-use shuttle_persist::PersistInstance;
 use tokio::task;
 
+pub mod auth;
+pub mod charts;
 pub mod commands;
 pub mod workers;
 pub mod types;
 pub mod handlers;
+pub mod ledger;
 pub mod utils;
 
 use crate::types::*;
@@ -55,11 +62,8 @@ pub mod consts {
 async fn serenity(
         #[shuttle_secrets::Secrets] secret_store: SecretStore,
         #[shuttle_shared_db::Postgres(local_uri = "{secrets.DATABASE_URL}")] pool: sqlx::postgres::PgPool,
-        #[shuttle_persist::Persist] persist_instance: PersistInstance
     ) -> shuttle_serenity::ShuttleSerenity {
     info!("Loading Economist Bot...");
-    
-    create_dir_all("data/").unwrap();
 
     let Some(discord_token) = secret_store.get("DISCORD_TOKEN") else {
         return Err(anyhow!("Failed to get DISCORD_TOKEN from Shuttle secret store").into())
@@ -67,9 +71,12 @@ async fn serenity(
 
     info!("Initialising SQL database...");
 
-    let Some(_guild_id) = secret_store.get("DISCORD_GUILD_ID") else {
+    let Some(guild_id) = secret_store.get("DISCORD_GUILD_ID") else {
         return Err(anyhow!("Failed to get DISCORD_GUILD_ID from Shuttle secret store").into())
     };
+    let Ok(guild_id) = guild_id.parse::<i64>() else {
+        return Err(anyhow!("DISCORD_GUILD_ID must be a Discord server ID").into())
+    };
 
     match sqlx_init(&pool).await {
         Ok(_) => {},
@@ -78,20 +85,40 @@ async fn serenity(
         }
     };
 
-    let password = match secret_store.get("DATABASE_PASSWORD") {
-        Some(p) => p,
-        None => {
-            warn!("WARNING: you have not set a database password in you Secrets.toml");
-            warn!("         This will allow ANYONE WITH PERMISSIONS to manage Economist Bot's database");
-            warn!("         You should change this ASAP.");
-            String::from("")
-        }
+    // Optional, as `/currency database` also needs each server's admin role for anything destructive
+    let password = secret_store.get("DATABASE_PASSWORD").filter(|password| !password.is_empty());
+    if password.is_none() {
+        warn!("No DATABASE_PASSWORD is set in Secrets.toml, so `/currency database` relies on each server's admin role alone");
+    }
+
+    // Days a deleted currency stays in the archive before it is purged, 0 keeps it forever
+    let archive_retention_days = match secret_store.get("ARCHIVE_RETENTION_DAYS").map(|days| days.parse::<i64>()) {
+        Some(Ok(days)) if days >= 0 => days,
+        Some(_) => return Err(anyhow!("ARCHIVE_RETENTION_DAYS must be a whole number of days").into()),
+        None => 30
     };
 
+    // Economies from before they were kept per server belong to the bot's own server
+    match DBManager::new(pool.clone(), guild_id).adopt_legacy_rows().await {
+        Ok(0) => {},
+        Ok(adopted) => info!("Moved {adopted} rows from before multi-guild support into guild {guild_id}"),
+        Err(e) => return Err(anyhow!("Error adopting existing currencies into guild {guild_id}: {e:?}").into())
+    }
+
     info!("Starting workers...");
-    let pool_clone = pool.clone();
-    let (_tx, rx) = futures::channel::mpsc::channel(8);
-    task::spawn(record_worker(persist_instance, pool_clone, rx));
+    // Workers start before the client, so they get their own connection to Discord for posting daily summaries
+    let http = Arc::new(Http::new(&discord_token));
+    let record_workers = RecordWorkers::new(pool.clone(), http);
+    record_workers.start(guild_id);
+    match DBQueryAgent::list_guilds(&pool).await {
+        Ok(guilds) => for guild in guilds {
+            record_workers.start(guild);
+        },
+        Err(e) => error!("Couldn't list guilds to start record workers for: {e:?}")
+    }
+    if archive_retention_days > 0 {
+        task::spawn(archive_worker(pool.clone(), archive_retention_days));
+    }
 
     let intents = GatewayIntents::GUILD_MESSAGES | GatewayIntents::DIRECT_MESSAGES | GatewayIntents::MESSAGE_CONTENT;
 
@@ -100,33 +127,61 @@ async fn serenity(
     let list_handler = Arc::new(Mutex::new(list::ListHandler::new()));
     let view_handler = Arc::new(Mutex::new(view::ViewHandler::new()));
     let create_handler = Arc::new(Mutex::new(create::CreateHandler::new()));
-    let delete_handler = Arc::new(Mutex::new(delete::DeleteHandler::new()));
+    let delete_handler = Arc::new(Mutex::new(delete::DeleteHandler::new(archive_retention_days)));
     let modify_handler = Arc::new(Mutex::new(modify::ModifyHandler::new()));
     let records_handler = Arc::new(Mutex::new(records::RecordsHandler::new()));
+    let forex_handler = Arc::new(Mutex::new(forex::ForexHandler::new()));
+    let transactions_handler = Arc::new(Mutex::new(transactions::TransactionsHandler::new()));
+    let revert_handler = Arc::new(Mutex::new(revert::RevertHandler::new()));
+    let owners_handler = Arc::new(Mutex::new(owners::OwnersHandler::new()));
+    let restore_handler = Arc::new(Mutex::new(restore::RestoreHandler::new()));
+    let compare_handler = Arc::new(Mutex::new(compare::CompareHandler::new()));
+    let market_handler = Arc::new(Mutex::new(market::MarketHandler::new(record_workers.clone())));
+    let worker_handler = Arc::new(Mutex::new(worker::WorkerHandler::new(record_workers.clone())));
     let database_handler = Arc::new(Mutex::new(database::DatabaseHandler::new(password)));
+    let admin_handler = Arc::new(Mutex::new(admin::AdminHandler::new()));
+    let ledger_handler = Arc::new(Mutex::new(handlers::ledger::LedgerHandler::new()));
+    let report_handler = Arc::new(Mutex::new(report::ReportHandler::new()));
+    let audit_handler = Arc::new(Mutex::new(audit::AuditHandler::new()));
 
     let cmd_handlers: Vec<Arc<Mutex<dyn ApplicationCommandHandler + Send + Sync>>> = vec![
         circulation_handler.clone(),
         reserve_handler.clone(),
         delete_handler.clone(),
         database_handler.clone(),
+        transactions_handler.clone(),
+        revert_handler.clone(),
+        owners_handler.clone(),
+        audit_handler.clone(),
         list_handler,
         view_handler,
         create_handler,
         modify_handler,
         records_handler,
+        forex_handler,
+        restore_handler,
+        compare_handler,
+        market_handler,
+        worker_handler,
+        admin_handler,
+        ledger_handler,
+        report_handler,
     ];
     let interaction_handlers: Vec<Arc<Mutex<dyn InteractionResponseHandler + Send + Sync>>> = vec![
         circulation_handler,
         reserve_handler,
         delete_handler,
+        transactions_handler,
+        revert_handler,
+        owners_handler,
+        audit_handler,
     ];
 
     let modal_handlers: Vec<Arc<Mutex<dyn ModalSubmitHandler + Send + Sync>>> = vec![
         database_handler
     ];
 
-    let client = match Client::builder(&discord_token, intents).event_handler(Handler::new(secret_store, pool, cmd_handlers, interaction_handlers, modal_handlers)).await{
+    let client = match Client::builder(&discord_token, intents).event_handler(Handler::new(secret_store, pool, cmd_handlers, interaction_handlers, modal_handlers, record_workers)).await{
         Ok(c) => c,
         Err(e) => return Err(anyhow!("Error creating client: {e:?}").into())
     };
@@ -143,6 +198,21 @@ pub struct CommandResponseObject {
     embed: Option<serenity::builder::CreateEmbed>,
     ephemeral: bool,
     modal: bool,
+    files: Vec<ResponseFile>,
+    ledger: Option<serenity::builder::CreateEmbed>, // Mirrored to the server's ledger channel once the interaction has been answered
+}
+
+#[derive(Clone)]
+pub struct ResponseFile {
+    filename: String,
+    data: Vec<u8>,
+}
+
+// Files can be megabytes of data, so only show what would be attached in debug dumps
+impl std::fmt::Debug for ResponseFile {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} ({} bytes)", self.filename, self.data.len())
+    }
 }
 
 
@@ -155,7 +225,9 @@ impl CommandResponseObject {
             feedback: None,
             embed: None,
             ephemeral,
-            modal: false
+            modal: false,
+            files: vec![],
+            ledger: None
         }
     }
 
@@ -167,7 +239,9 @@ impl CommandResponseObject {
             feedback: None,
             embed: None,
             ephemeral,
-            modal: false
+            modal: false,
+            files: vec![],
+            ledger: None
         }
     }
 
@@ -179,7 +253,9 @@ impl CommandResponseObject {
             feedback: None,
             embed: None,
             ephemeral: true,
-            modal: true
+            modal: true,
+            files: vec![],
+            ledger: None
         }
     }
 
@@ -191,7 +267,9 @@ impl CommandResponseObject {
             feedback: Some(feedback.into()),
             embed: None,
             ephemeral,
-            modal: false
+            modal: false,
+            files: vec![],
+            ledger: None
         }
     }
 
@@ -203,7 +281,9 @@ impl CommandResponseObject {
             feedback: None,
             embed: None,
             ephemeral: false,
-            modal: false
+            modal: false,
+            files: vec![],
+            ledger: None
         }
     }
 
@@ -215,7 +295,9 @@ impl CommandResponseObject {
             feedback: None,
             embed: Some(data),
             ephemeral: false,
-            modal: false
+            modal: false,
+            files: vec![],
+            ledger: None
         }
     }
     
@@ -227,10 +309,39 @@ impl CommandResponseObject {
             feedback: None,
             embed: None,
             ephemeral: true,
-            modal: false
+            modal: false,
+            files: vec![],
+            ledger: None
         }
     }
 
+    pub fn with_file(mut self, filename: impl Into<String>, data: Vec<u8>) -> Self {
+        self.files.push(ResponseFile {
+            filename: filename.into(),
+            data
+        });
+        self
+    }
+
+    pub fn with_ledger(mut self, embed: serenity::builder::CreateEmbed) -> Self {
+        self.ledger = Some(embed);
+        self
+    }
+
+    pub fn get_ledger(&self) -> Option<serenity::builder::CreateEmbed> {
+        self.ledger.clone()
+    }
+
+    pub fn add_files<'a, 'b>(&self, message: &'b mut CreateInteractionResponseData<'a>) -> &'b mut CreateInteractionResponseData<'a> {
+        for file in &self.files {
+            message.add_file(AttachmentType::Bytes {
+                data: Cow::Owned(file.data.clone()),
+                filename: file.filename.clone()
+            });
+        }
+        message
+    }
+
     pub fn is_interactive(&self) -> bool {
         self.interactive
     }
@@ -265,25 +376,48 @@ impl CommandResponseObject {
 }
 
 struct Handler {
-    db_manager: DBManager,
-    query_agent: DBQueryAgent,
+    pool: sqlx::postgres::PgPool,
     application_command_handlers: Vec<Arc<Mutex<dyn ApplicationCommandHandler + Send + Sync>>>,
     interaction_response_handlers: Vec<Arc<Mutex<dyn InteractionResponseHandler + Send + Sync>>>,
-    modal_submit_handlers: Vec<Arc<Mutex<dyn ModalSubmitHandler + Send + Sync>>>
+    modal_submit_handlers: Vec<Arc<Mutex<dyn ModalSubmitHandler + Send + Sync>>>,
+    record_workers: RecordWorkers
 }
 
+// Shown when a command is used outside a server, where there is no economy to act on
+const NO_GUILD_MESSAGE: &str = "Economist Bot's currencies belong to a server, so this can only be used in one";
+
 impl Handler {
-    fn new(_secrets: SecretStore, pool: sqlx::postgres::PgPool, cmd_handlers: Vec<Arc<Mutex<dyn ApplicationCommandHandler + Send + Sync>>>, interaction_handlers: Vec<Arc<Mutex<dyn InteractionResponseHandler + Send + Sync>>>, modal_handlers: Vec<Arc<Mutex<dyn ModalSubmitHandler + Send + Sync>>>) -> Self {
-        let db_manager = DBManager::new(pool.clone());
-        let query_agent = DBQueryAgent::new(pool);
+    fn new(_secrets: SecretStore, pool: sqlx::postgres::PgPool, cmd_handlers: Vec<Arc<Mutex<dyn ApplicationCommandHandler + Send + Sync>>>, interaction_handlers: Vec<Arc<Mutex<dyn InteractionResponseHandler + Send + Sync>>>, modal_handlers: Vec<Arc<Mutex<dyn ModalSubmitHandler + Send + Sync>>>, record_workers: RecordWorkers) -> Self {
         Handler {
-            db_manager,
-            query_agent,
+            pool,
             application_command_handlers: cmd_handlers,
             interaction_response_handlers: interaction_handlers,
-            modal_submit_handlers: modal_handlers
+            modal_submit_handlers: modal_handlers,
+            record_workers
         }
     }
+
+    // Each interaction only sees the economy of the server it came from, whose record worker is started if this is the first time it's been seen
+    fn guild_agents(&self, guild_id: Option<GuildId>) -> Option<(DBQueryAgent, DBManager)> {
+        let guild_id = guild_id?.0 as i64;
+        self.record_workers.start(guild_id);
+        Some((DBQueryAgent::new(self.pool.clone(), guild_id), DBManager::new(self.pool.clone(), guild_id)))
+    }
+
+    async fn post_ledger(&self, http: &Http, guild_id: Option<GuildId>, content: &CommandResponseObject) {
+        let (Some(embed), Some(guild_id)) = (content.get_ledger(), guild_id) else {
+            return
+        };
+        ledger::post(http, &DBQueryAgent::new(self.pool.clone(), guild_id.0 as i64), embed).await;
+    }
+}
+
+// The client only drops its handler when shutting down, so stop the record workers with it. Any session in progress is resumed on the next start
+impl Drop for Handler {
+    fn drop(&mut self) {
+        info!("Shutting down, halting workers...");
+        self.record_workers.halt_all();
+    }
 }
 
 #[async_trait]
@@ -304,8 +438,12 @@ impl<'a> EventHandler for Handler {
                 } else {
                     if let Some(sub_command) = cmd.data.options.get(0) {
                         if sub_command.name.as_str() == name {
+                            let Some((query_agent, manager)) = self.guild_agents(cmd.guild_id) else {
+                                content = CommandResponseObject::error(NO_GUILD_MESSAGE);
+                                continue
+                            };
                             let mut lock = handler.lock().await;
-                            content = match lock.handle_application_command(&cmd, &self.query_agent, &self.db_manager).await {
+                            content = match lock.handle_application_command(&cmd, &query_agent, &manager).await {
                                 Ok(data) => data.clone(),
                                 Err(e) => CommandResponseObject::error(format!("Error responding to application command: {e:?}"))
                             };
@@ -334,7 +472,7 @@ impl<'a> EventHandler for Handler {
                         response
                             .kind(InteractionResponseType::ChannelMessageWithSource)
                             .interaction_response_data(|message| {
-                                message
+                                content.add_files(message)
                                     .set_embed(embed)
                                     .title(cmd.data.name.clone())
                                     .ephemeral(content.is_ephemeral())
@@ -350,7 +488,7 @@ impl<'a> EventHandler for Handler {
                             .create_interaction_response(&cx.http, |response| {
                                 response
                                     .kind(InteractionResponseType::ChannelMessageWithSource)
-                                    .interaction_response_data(|message| message
+                                    .interaction_response_data(|message| content.add_files(message)
                                                                .set_components(content.get_interactive_data().clone())
                                                                .content(content.get_text().clone())
                                                                .ephemeral(content.is_ephemeral())
@@ -367,7 +505,7 @@ impl<'a> EventHandler for Handler {
                                 response
                                     .kind(InteractionResponseType::ChannelMessageWithSource)
                                     .interaction_response_data(|message| 
-                                                               message
+                                                               content.add_files(message)
                                                                .content(content.get_text())
                                                                .ephemeral(content.is_ephemeral())
                                     )
@@ -380,18 +518,21 @@ impl<'a> EventHandler for Handler {
                     }
                 }
             }
+            self.post_ledger(&cx.http, cmd.guild_id, &content).await;
         } else if let Interaction::MessageComponent(cmd) = interaction {
             let mut content = CommandResponseObject::error("Got no response from interaction response handler");
+            let (callsign, _) = utils::split_custom_id(cmd.data.custom_id.as_str());
             for interaction_response in &self.interaction_response_handlers {
-                let interaction_pattern;
-                let guard = interaction_response.lock().await;
-                interaction_pattern = guard.get_pattern();
-                for interaction_callsign in interaction_pattern.clone() {
-                    if interaction_callsign == cmd.data.custom_id.as_str() {
-                        content = match guard.handle_interaction_response(&cmd, &self.query_agent, &self.db_manager).await {
-                            Ok(data) => data,
-                            Err(e) => CommandResponseObject::error(format!("{e:?}"))
-                        }
+                let mut guard = interaction_response.lock().await;
+                let matched = guard.get_pattern().contains(&callsign);
+                if matched {
+                    let Some((query_agent, manager)) = self.guild_agents(cmd.guild_id) else {
+                        content = CommandResponseObject::error(NO_GUILD_MESSAGE);
+                        continue
+                    };
+                    content = match guard.handle_interaction_response(&cmd, &query_agent, &manager).await {
+                        Ok(data) => data,
+                        Err(e) => CommandResponseObject::error(format!("{e:?}"))
                     }
                 }
             }
@@ -406,7 +547,7 @@ impl<'a> EventHandler for Handler {
                         .create_interaction_response(&cx.http, |response| {
                             response
                                 .kind(InteractionResponseType::ChannelMessageWithSource)
-                                .interaction_response_data(|message| message
+                                .interaction_response_data(|message| content.add_files(message)
                                                            .set_components(content.get_interactive_data().clone())
                                                            .content(content.get_feedback().clone())
                                                            .ephemeral(content.is_ephemeral())
@@ -425,7 +566,7 @@ impl<'a> EventHandler for Handler {
                         .create_interaction_response(&cx.http, |response| {
                             response
                                 .kind(InteractionResponseType::ChannelMessageWithSource)
-                                .interaction_response_data(|message| message
+                                .interaction_response_data(|message| content.add_files(message)
                                                            .content(content.get_text())
                                                            .ephemeral(content.is_ephemeral()))
                         })
@@ -438,25 +579,27 @@ impl<'a> EventHandler for Handler {
                     }
                 }
             }
+            self.post_ledger(&cx.http, cmd.guild_id, &content).await;
         } else if let Interaction::ModalSubmit(cmd) = interaction {
             let mut content = CommandResponseObject::error("Got no response from interaction response handler");
             info!("Command data: {cmd:#?}");
-            info!("Matching on callsign: {}", cmd.data.custom_id.clone());
+            let (callsign, _) = utils::split_custom_id(cmd.data.custom_id.as_str());
+            info!("Matching on callsign: {callsign}");
             for interaction_response in &self.modal_submit_handlers {
-                let interaction_pattern;
-                let guard = interaction_response.lock().await;
-                interaction_pattern = guard.get_pattern();
-                for interaction_callsign in interaction_pattern.clone() {
-                    info!("Checking callsign {interaction_callsign}");
-                    if interaction_callsign == cmd.data.custom_id.as_str() {
-                        info!("Got a match!");
-                        content = match guard.handle_modal_submit(&cmd, &self.query_agent, &self.db_manager).await {
-                            Ok(data) => {
-                                info!("Data: {data:#?}");
-                                data
-                            },
-                            Err(e) => CommandResponseObject::error(format!("{e:?}"))
-                        }
+                let mut guard = interaction_response.lock().await;
+                let matched = guard.get_pattern().contains(&callsign);
+                if matched {
+                    info!("Got a match!");
+                    let Some((query_agent, manager)) = self.guild_agents(cmd.guild_id) else {
+                        content = CommandResponseObject::error(NO_GUILD_MESSAGE);
+                        continue
+                    };
+                    content = match guard.handle_modal_submit(&cmd, &query_agent, &manager).await {
+                        Ok(data) => {
+                            info!("Data: {data:#?}");
+                            data
+                        },
+                        Err(e) => CommandResponseObject::error(format!("{e:?}"))
                     }
                 }
             }
@@ -466,7 +609,7 @@ impl<'a> EventHandler for Handler {
                         .create_interaction_response(&cx.http, |response| {
                             response
                                 .kind(InteractionResponseType::ChannelMessageWithSource)
-                                .interaction_response_data(|message| message
+                                .interaction_response_data(|message| content.add_files(message)
                                                            .set_components(content.get_interactive_data().clone())
                                                            .content(content.get_feedback().clone())
                                                            .ephemeral(content.is_ephemeral())
@@ -485,7 +628,7 @@ impl<'a> EventHandler for Handler {
                         .create_interaction_response(&cx.http, |response| {
                             response
                                 .kind(InteractionResponseType::ChannelMessageWithSource)
-                                .interaction_response_data(|message| message
+                                .interaction_response_data(|message| content.add_files(message)
                                                            .content(content.get_text())
                                                            .ephemeral(content.is_ephemeral())
                                 )
@@ -499,6 +642,7 @@ impl<'a> EventHandler for Handler {
                     }*/
                 }
             }
+            self.post_ledger(&cx.http, cmd.guild_id, &content).await;
         }
     }
 
@@ -544,56 +688,15 @@ impl<'a> EventHandler for Handler {
     }
 }
 
+// Schema changes go in a new numbered file under `migrations/`, never by editing an applied one
+pub static MIGRATOR: Migrator = sqlx::migrate!("./migrations");
+
 async fn sqlx_init(pool: &sqlx::postgres::PgPool) -> Result<(), sqlx::Error> {
     let postgres_version: String = sqlx::query("SELECT version()").fetch_one(pool).await?.try_get("version")?; 
 
     info!("PostgreSQL version: {}", postgres_version);
 
-    sqlx::query("CREATE TABLE IF NOT EXISTS currencies(
-        currency_id BIGSERIAL NOT NULL,
-        currency_code TEXT NOT NULL UNIQUE,
-        currency_name TEXT NOT NULL,
-        state TEXT NOT NULL,
-        circulation BIGINT NOT NULL,
-        reserves BIGINT NOT NULL,
-        owner TEXT NOT NULL,
-        value DOUBLE PRECISION GENERATED ALWAYS AS (
-            CASE WHEN reserves <= 0 THEN 0
-                 WHEN circulation <= 0 THEN 0 
-                 ELSE (
-                     CAST(reserves AS DOUBLE PRECISION) / CAST(circulation AS DOUBLE PRECISION)
-                 ) 
-                 END
-            ) STORED,
-        PRIMARY KEY (currency_id)
-    );").execute(pool).await?;
-    sqlx::query("CREATE TABLE IF NOT EXISTS transactions(
-        transaction_id BIGSERIAL NOT NULL,
-        transaction_date TIMESTAMP WITHOUT TIME ZONE NOT NULL,
-        currency_id BIGINT NOT NULL,
-        delta_circulation BIGINT,
-        delta_reserves BIGINT,
-        initiator TEXT NOT NULL,
-        PRIMARY KEY (transaction_id),
-        FOREIGN KEY (currency_id) REFERENCES currencies(currency_id) ON DELETE CASCADE
-    )").execute(pool).await?;
-    sqlx::query("CREATE TABLE IF NOT EXISTS records(
-        record_id BIGSERIAL NOT NULL,
-        record_date DATE NOT NULL,
-        currency_id BIGINT NOT NULL,
-        opening_value DOUBLE PRECISION,
-        closing_value DOUBLE PRECISION,
-        delta_value DOUBLE PRECISION GENERATED ALWAYS AS (closing_value - opening_value) STORED,
-        growth SMALLINT GENERATED ALWAYS AS (
-            CASE WHEN (closing_value - opening_value) = 0 THEN 0
-                 WHEN (closing_value - opening_value) > 0 THEN 1
-                 ELSE -1
-                 END
-            ) STORED,
-        PRIMARY KEY (record_id),
-        FOREIGN KEY (currency_id) REFERENCES currencies(currency_id) ON DELETE CASCADE
-        )
-    ").execute(pool).await?;
+    MIGRATOR.run(pool).await?;
     Ok(())
 }
 
@@ -1,4 +1,5 @@
-use chrono::{offset::Utc, DateTime, NaiveDate};
+use chrono::{offset::Utc, DateTime, Datelike, Duration, LocalResult, NaiveDate, NaiveDateTime, NaiveTime, TimeZone, Weekday};
+use chrono_tz::Tz;
 use async_trait::async_trait;
 use crate::CommandResponseObject;
 use serenity::model::prelude::interaction::application_command::ApplicationCommandInteraction;
@@ -6,10 +7,13 @@ use serenity::model::prelude::command::CommandOptionType;
 use serenity::builder::CreateApplicationCommandOption;
 use serenity::model::application::interaction::message_component::MessageComponentInteraction;
 use serenity::model::application::interaction::modal::ModalSubmitInteraction;
+use serenity::model::user::User;
 use crate::commands::query::*;
 use crate::commands::manage::*;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
 
-#[derive(sqlx::FromRow, Debug, Clone, Default)]
+#[derive(sqlx::FromRow, Serialize, Deserialize, Debug, Clone, Default)]
 pub struct CurrencyData {
     pub currency_id: i64,
     pub currency_name: String,
@@ -17,20 +21,67 @@ pub struct CurrencyData {
     pub circulation: i64,
     pub reserves: i64,
     pub owner: String,
+    pub owner_id: Option<i64>, // None for currencies created before owners were stored by user ID
     pub value: f64,
     pub state: String,
+    #[serde(default)]
+    pub archived_at: Option<DateTime<Utc>>, // Set while the currency is in the archive after `/currency delete`
 }
 
-#[derive(sqlx::FromRow, Debug, Clone, Default)]
+impl CurrencyData {
+    // Names can be changed or reused, so currencies without an owner ID aren't owned by anyone until an admin transfers them
+    pub fn is_owned_by(&self, user: &User) -> bool {
+        self.owner_id == Some(user.id.0 as i64)
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum CurrencyPermission {
+    Reserve,
+    Circulation,
+    Metadata,
+    Delete,
+    Owner, // Only ever granted to the currency's owner, never to co-owners
+}
+
+#[derive(sqlx::FromRow, Serialize, Deserialize, Debug, Clone, Default)]
+pub struct ManagerData {
+    pub currency_id: i64,
+    pub user_id: i64,
+    pub can_reserve: bool,
+    pub can_circulation: bool,
+    pub can_metadata: bool,
+    pub can_delete: bool,
+}
+
+impl ManagerData {
+    pub fn allows(&self, permission: CurrencyPermission) -> bool {
+        match permission {
+            CurrencyPermission::Reserve => self.can_reserve,
+            CurrencyPermission::Circulation => self.can_circulation,
+            CurrencyPermission::Metadata => self.can_metadata,
+            CurrencyPermission::Delete => self.can_delete,
+            CurrencyPermission::Owner => false
+        }
+    }
+}
+
+#[derive(sqlx::FromRow, Serialize, Deserialize, Debug, Clone, Default)]
 pub struct TransactionData {
     pub transaction_id: i64,
     pub transaction_date: DateTime<Utc>,
+    pub currency_id: i64,
     pub currency_code: String,
     pub delta_reserves: Option<i64>,
     pub delta_circulation: Option<i64>,
+    pub initiator: String, // Name at the time of the transaction, for display only
+    #[serde(default)]
+    pub initiator_id: Option<i64>, // None for transactions made before initiators were stored by user ID
+    pub reverts_transaction_id: Option<i64>,
+    pub reverted_by: Option<i64>,
 }
 
-#[derive(sqlx::FromRow, Debug, Clone, Default)]
+#[derive(sqlx::FromRow, Serialize, Deserialize, Debug, Clone, Default)]
 pub struct RecordData {
     pub record_id: i64,
     pub record_date: NaiveDate,
@@ -39,12 +90,221 @@ pub struct RecordData {
     pub closing_value: f64,
     pub delta_value: f64,
     pub growth: i16, // -1 for decline, 0 for steady, 1 for growth
+    #[serde(default)]
+    pub closing_circulation: Option<i64>,
+    #[serde(default)]
+    pub closing_reserves: Option<i64>,
+    #[serde(default)]
+    pub interpolated: bool, // Estimated rather than observed, e.g. for days the bot was offline
+    #[serde(default)]
+    pub high_value: Option<f64>, // Highest value seen during the day, from snapshots
+    #[serde(default)]
+    pub low_value: Option<f64>,
+}
+
+impl RecordData {
+    // Records without snapshots still moved between their opening and closing values
+    pub fn high(&self) -> f64 {
+        self.high_value.unwrap_or(self.opening_value.max(self.closing_value))
+    }
+
+    pub fn low(&self) -> f64 {
+        self.low_value.unwrap_or(self.opening_value.min(self.closing_value))
+    }
+}
+
+// One state-changing command, with what it changed
+#[derive(sqlx::FromRow, Debug, Clone)]
+pub struct AuditEntry {
+    pub audit_id: i64,
+    pub audit_date: DateTime<Utc>,
+    pub actor_id: i64,
+    pub actor_name: String,
+    pub action: String,
+    pub currency_id: Option<i64>,
+    pub currency_code: Option<String>, // None once the currency has been purged
+    pub details: Option<String>,
+    pub before_data: Option<Value>,
+    pub after_data: Option<Value>,
+}
+
+impl AuditEntry {
+    // Fields that differ between before and after, or every field when only one side exists
+    pub fn changes(&self) -> Vec<String> {
+        let describe = |value: &Value| match value {
+            Value::String(s) => s.clone(),
+            Value::Null => "none".into(),
+            other => other.to_string()
+        };
+
+        match (&self.before_data, &self.after_data) {
+            (Some(Value::Object(before)), Some(Value::Object(after))) => after.iter()
+                .filter(|(key, value)| before.get(*key) != Some(*value))
+                .map(|(key, value)| format!("{key}: {} → {}", before.get(key).map_or("none".into(), describe), describe(value)))
+                .collect(),
+            (Some(Value::Object(fields)), None) | (None, Some(Value::Object(fields))) => fields.iter()
+                .map(|(key, value)| format!("{key}: {}", describe(value)))
+                .collect(),
+            _ => vec![]
+        }
+    }
+}
+
+// Contents of a `/currency database export` backup file
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct DatabaseExport {
+    pub schema_version: i64, // Latest migration applied to the database the export was taken from
+    pub exported_at: DateTime<Utc>,
+    pub currencies: Vec<CurrencyData>,
+    pub managers: Vec<ManagerData>,
+    pub transactions: Vec<TransactionData>,
+    pub records: Vec<RecordData>,
+}
+
+#[derive(sqlx::FromRow, Serialize, Debug, Clone)]
+pub struct GuildSettings {
+    pub guild_id: i64,
+    pub admin_role_id: Option<i64>, // None until a server manager picks one, which leaves the dangerous commands disabled
+    pub ledger_channel_id: Option<i64>,
+    pub report_channel_id: Option<i64>, // Falls back to `ledger_channel_id` when None
+}
+
+impl GuildSettings {
+    pub fn new(guild_id: i64) -> Self {
+        GuildSettings {
+            guild_id,
+            admin_role_id: None,
+            ledger_channel_id: None,
+            report_channel_id: None
+        }
+    }
+}
+
+// When the market opens and closes for a guild, used by the record worker to schedule snapshots
+#[derive(sqlx::FromRow, Serialize, Debug, Clone)]
+pub struct MarketConfig {
+    pub guild_id: i64,
+    pub open_time: NaiveTime, // Local time in `timezone`
+    pub close_time: NaiveTime, // Before or equal to `open_time` for sessions that run overnight
+    pub timezone: String, // IANA name, e.g. `Europe/London`
+    pub trading_days: i16, // Bitmask of the days sessions open on, Monday is bit 0
+    pub snapshot_interval_minutes: i32, // 0 to only take snapshots after transactions
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MarketSession {
+    pub opens_at: DateTime<Utc>,
+    pub closes_at: DateTime<Utc>,
+}
+
+impl MarketConfig {
+    pub fn new(guild_id: i64) -> Self {
+        MarketConfig {
+            guild_id,
+            open_time: NaiveTime::from_hms_opt(6, 0, 0).unwrap(),
+            close_time: NaiveTime::from_hms_opt(18, 0, 0).unwrap(),
+            timezone: "UTC".into(),
+            trading_days: 0b111_1111,
+            snapshot_interval_minutes: 60
+        }
+    }
+
+    pub fn tz(&self) -> Tz {
+        self.timezone.parse().unwrap_or(Tz::UTC)
+    }
+
+    pub fn trades_on(&self, day: Weekday) -> bool {
+        self.trading_days & (1 << day.num_days_from_monday()) != 0
+    }
+
+    // The session in progress at `after`, or the next one to open. None if there are no trading days
+    pub fn next_session(&self, after: DateTime<Utc>) -> Option<MarketSession> {
+        let today = after.with_timezone(&self.tz()).date_naive();
+
+        // Starting from yesterday catches an overnight session that opened the day before
+        (-1..=7)
+            .filter_map(|offset| self.session_on(today + Duration::days(offset)))
+            .find(|session| session.closes_at > after)
+    }
+
+    // The session that opens on a local date, None if the market doesn't trade that day
+    pub fn session_on(&self, date: NaiveDate) -> Option<MarketSession> {
+        if !self.trades_on(date.weekday()) {
+            return None
+        }
+
+        let tz = self.tz();
+        let close_date = if self.close_time <= self.open_time { date + Duration::days(1) } else { date };
+        Some(MarketSession {
+            opens_at: local_to_utc(&tz, date.and_time(self.open_time)),
+            closes_at: local_to_utc(&tz, close_date.and_time(self.close_time))
+        })
+    }
+
+    pub fn describe_days(&self) -> String {
+        let days: Vec<String> = WEEKDAYS.iter()
+            .filter(|day| self.trades_on(**day))
+            .map(|day| day.to_string())
+            .collect();
+        match days.len() {
+            0 => "never".into(),
+            7 => "every day".into(),
+            _ => days.join(", ")
+        }
+    }
+}
+
+pub const WEEKDAYS: [Weekday; 7] = [Weekday::Mon, Weekday::Tue, Weekday::Wed, Weekday::Thu, Weekday::Fri, Weekday::Sat, Weekday::Sun];
+
+// Times skipped when the clocks go forward are moved an hour later, repeated times use the first occurrence
+fn local_to_utc(tz: &Tz, local: NaiveDateTime) -> DateTime<Utc> {
+    match tz.from_local_datetime(&local) {
+        LocalResult::Single(time) => time.with_timezone(&Utc),
+        LocalResult::Ambiguous(earliest, _) => earliest.with_timezone(&Utc),
+        LocalResult::None => local_to_utc(tz, local + Duration::hours(1))
+    }
+}
+
+// A trading day, stored while it is in progress so it can be resumed after a restart
+#[derive(sqlx::FromRow, Debug, Clone)]
+pub struct SessionData {
+    pub session_id: i64,
+    pub guild_id: i64,
+    pub record_date: NaiveDate, // Local date in the market's timezone when the session opened
+    pub opened_at: DateTime<Utc>,
+    pub closes_at: DateTime<Utc>,
+    pub closed_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone)]
+pub struct PendingTransaction {
+    pub currency_code: String,
+    pub amount: i64,
+    pub initiator: User,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ExchangeRate {
+    pub from: CurrencyData,
+    pub to: CurrencyData,
+    pub rate: Option<f64>, // units of `to` per unit of `from`, None if `to` has no value
+    pub inverse_rate: Option<f64>, // units of `from` per unit of `to`, None if `from` has no value
+}
+
+impl ExchangeRate {
+    pub fn convert(&self, amount: f64) -> Option<f64> {
+        self.rate.map(|rate| amount * rate)
+    }
 }
 
 #[derive(Debug, Clone, Default)]
 pub enum WorkerMessage {
     #[default]
     Halt,
+    Reschedule, // Market hours have changed and should be reloaded
+    ForceOpen, // Open now, until the next scheduled close
+    ForceClose, // Close now and write the day's records
+    RebuildRecords(NaiveDate), // Rebuild a day's records out of its snapshots
 }
 
 #[async_trait]
@@ -62,12 +322,12 @@ pub trait ApplicationCommandHandler {
 
 #[async_trait]
 pub trait InteractionResponseHandler {
-    async fn handle_interaction_response(&self, data: &MessageComponentInteraction, query_agent: &DBQueryAgent, manager: &DBManager) -> Result<CommandResponseObject, String>;
+    async fn handle_interaction_response(&mut self, data: &MessageComponentInteraction, query_agent: &DBQueryAgent, manager: &DBManager) -> Result<CommandResponseObject, String>;
     fn get_pattern(&self) -> Vec<&str>;
 }
 
 #[async_trait]
 pub trait ModalSubmitHandler {
-    async fn handle_modal_submit(&self, data: &ModalSubmitInteraction, query_agent: &DBQueryAgent, manager: &DBManager) -> Result<CommandResponseObject, String>;
+    async fn handle_modal_submit(&mut self, data: &ModalSubmitInteraction, query_agent: &DBQueryAgent, manager: &DBManager) -> Result<CommandResponseObject, String>;
     fn get_pattern(&self) -> Vec<&str>;
 }
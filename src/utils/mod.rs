@@ -1,4 +1,9 @@
+pub mod pending;
+
+use chrono::{Duration, NaiveDate, Utc};
+use serenity::builder::CreateApplicationCommandOption;
 use serenity::model::application::interaction::application_command::CommandDataOption;
+use serenity::model::prelude::command::CommandOptionType;
 use serenity::model::prelude::interaction::application_command::ApplicationCommandInteraction;
 
 pub fn get_options(data: &ApplicationCommandInteraction) -> Result<Vec<CommandDataOption>, String> {
@@ -20,3 +25,50 @@ pub fn get_options(data: &ApplicationCommandInteraction) -> Result<Vec<CommandDa
 
     Ok(action.options.clone())
 }
+
+// Custom ids take the form `callsign:token`, where the token identifies a pending action
+pub fn custom_id(callsign: &str, token: &str) -> String {
+    format!("{callsign}:{token}")
+}
+
+pub fn split_custom_id(custom_id: &str) -> (&str, Option<&str>) {
+    match custom_id.split_once(':') {
+        Some((callsign, token)) => (callsign, Some(token)),
+        None => (custom_id, None)
+    }
+}
+
+// Graph time range option shared by every command that draws records
+pub fn range_option() -> CreateApplicationCommandOption {
+    CreateApplicationCommandOption::default()
+        .kind(CommandOptionType::String)
+        .name("range")
+        .description("How far back the graph goes (default: 30 days)")
+        .add_string_choice("7 days", "7d")
+        .add_string_choice("30 days", "30d")
+        .add_string_choice("90 days", "90d")
+        .add_string_choice("All time", "all")
+        .clone()
+}
+
+// Earliest record date to include for a `range` option value, None for all records
+pub fn range_start(range: &str) -> Option<NaiveDate> {
+    let days = match range {
+        "7d" => 7,
+        "90d" => 90,
+        "all" => return None,
+        _ => 30
+    };
+    Some(Utc::now().date_naive() - Duration::days(days))
+}
+
+// Chart colour scheme option shared by every command that draws records
+pub fn theme_option() -> CreateApplicationCommandOption {
+    CreateApplicationCommandOption::default()
+        .kind(CommandOptionType::String)
+        .name("theme")
+        .description("Colour scheme for the graph (default: dark)")
+        .add_string_choice("Dark", "dark")
+        .add_string_choice("Light", "light")
+        .clone()
+}
@@ -0,0 +1,45 @@
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+use uuid::Uuid;
+
+// Discord interaction tokens are only valid for 15 minutes, so there's no point keeping actions any longer
+pub const DEFAULT_LIFETIME: Duration = Duration::from_secs(15 * 60);
+
+pub struct PendingActions<T> {
+    actions: HashMap<String, (Instant, T)>,
+    lifetime: Duration
+}
+
+impl<T> PendingActions<T> {
+    pub fn new(lifetime: Duration) -> Self {
+        PendingActions {
+            actions: HashMap::new(),
+            lifetime
+        }
+    }
+
+    pub fn insert(&mut self, action: T) -> String {
+        self.purge_expired();
+        let token = Uuid::new_v4().simple().to_string();
+        self.actions.insert(token.clone(), (Instant::now() + self.lifetime, action));
+        token
+    }
+
+    pub fn take(&mut self, token: &str) -> Option<T> {
+        match self.actions.remove(token) {
+            Some((expiry, action)) if expiry > Instant::now() => Some(action),
+            _ => None
+        }
+    }
+
+    fn purge_expired(&mut self) {
+        let now = Instant::now();
+        self.actions.retain(|_, (expiry, _)| *expiry > now);
+    }
+}
+
+impl<T> Default for PendingActions<T> {
+    fn default() -> Self {
+        PendingActions::new(DEFAULT_LIFETIME)
+    }
+}
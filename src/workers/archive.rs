@@ -0,0 +1,30 @@
+use crate::commands::manage::DBManager;
+use crate::commands::query::DBQueryAgent;
+use tracing::{info, error};
+use sqlx::postgres::PgPool;
+use chrono::{Duration, offset::Utc};
+use tokio::time::sleep;
+
+pub async fn archive_worker(pool: PgPool, retention_days: i64) {
+    info!("Starting archive worker, purging currencies archived for over {retention_days} days...");
+
+    loop {
+        let guilds = match DBQueryAgent::list_guilds(&pool).await {
+            Ok(g) => g,
+            Err(e) => {
+                error!("Couldn't list guilds to purge archived currencies for: {e:?}");
+                vec![]
+            }
+        };
+        let archived_before = Utc::now() - Duration::days(retention_days);
+        for guild_id in guilds {
+            match DBManager::new(pool.clone(), guild_id).purge_archived_currencies(archived_before).await {
+                Ok(purged) => for currency in purged {
+                    info!("Purged archived currency `{}` ({}) from guild {guild_id}", currency.currency_code, currency.currency_name);
+                },
+                Err(e) => error!("Couldn't purge archived currencies for guild {guild_id}: {e:?}")
+            }
+        }
+        sleep(Duration::hours(1).to_std().unwrap()).await;
+    }
+}
@@ -1,203 +1,421 @@
 use crate::commands::query::DBQueryAgent;
 use crate::commands::manage::DBManager;
+use crate::charts::{self, ChartTheme};
+use crate::ledger;
 use tracing::{info, warn, error};
 use sqlx::postgres::PgPool;
-use shuttle_persist::PersistInstance;
-use chrono::{NaiveTime, offset::Utc};
+use chrono::{DateTime, Duration, NaiveDate, Datelike, offset::Utc};
 use crate::types::*;
 use futures::channel::mpsc;
+use futures::{StreamExt, TryStreamExt};
 use std::collections::HashMap;
-use tokio::time::sleep;
-use plotters::prelude::*;
-use plotters::style::colors::full_palette::*;
-
-pub async fn record_worker(_persist: PersistInstance, pool: PgPool, mut rx: mpsc::Receiver<WorkerMessage>) {
-    info!("Starting records worker...");
-    /*let mut last_date: DateTime<Utc> = match persist.load("last-record-time") {
-        Ok(datetime) => datetime,
-        Err(e) => {
-            warn!("Couldn't load persisted object 'last-record-time': {e:?}");
-            Utc::now()
+use std::sync::{Arc, Mutex};
+use serenity::http::Http;
+use tokio::task;
+use tokio::time::{sleep, timeout};
+
+// How long to wait before trying again when the database can't be reached at opening or closing
+const RETRY_DELAY_SECONDS: i64 = 30;
+
+// How long to sleep when no trading days are configured, in case they are set without a reschedule message
+const IDLE_HOURS: i64 = 24;
+
+// Closing this long after the scheduled time means the closing values weren't observed at the close, so the records are flagged as interpolated
+const LATE_CLOSE_MINUTES: i64 = 5;
+
+// The market report charts this many of the day's biggest movers over this many days
+const REPORT_CHART_CURRENCIES: usize = 5;
+const REPORT_CHART_DAYS: i64 = 14;
+
+enum MarketEvent {
+    Open(MarketSession),
+    Snapshot,
+    Close,
+}
+
+// Every server trades on its own market hours, so each gets its own record worker. A worker is started the first time its server is seen
+#[derive(Clone)]
+pub struct RecordWorkers {
+    pool: PgPool,
+    http: Arc<Http>,
+    senders: Arc<Mutex<HashMap<i64, mpsc::Sender<WorkerMessage>>>>
+}
+
+impl RecordWorkers {
+    pub fn new(pool: PgPool, http: Arc<Http>) -> Self {
+        RecordWorkers {
+            pool,
+            http,
+            senders: Arc::new(Mutex::new(HashMap::new()))
         }
-    };
+    }
+
+    pub fn start(&self, guild_id: i64) -> mpsc::Sender<WorkerMessage> {
+        let mut senders = self.senders.lock().unwrap();
+        senders.entry(guild_id)
+            .or_insert_with(|| {
+                let (tx, rx) = mpsc::channel(8);
+                task::spawn(record_worker(self.pool.clone(), self.http.clone(), guild_id, rx));
+                tx
+            })
+            .clone()
+    }
 
-    let time_now = Utc::now();
+    pub fn send(&self, guild_id: i64, message: WorkerMessage) -> Result<(), mpsc::TrySendError<WorkerMessage>> {
+        self.start(guild_id).try_send(message)
+    }
 
-    if time_now.signed_duration_since(last_date).num_hours() >= 12 {
-        record_update(pool).await;
-    }*/
+    pub fn halt_all(&self) {
+        for (guild_id, sender) in self.senders.lock().unwrap().iter_mut() {
+            if let Err(e) = sender.try_send(WorkerMessage::Halt) {
+                error!("Couldn't halt the record worker for guild {guild_id}: {e:?}");
+            }
+        }
+    }
+}
+
+pub async fn record_worker(pool: PgPool, http: Arc<Http>, guild_id: i64, mut rx: mpsc::Receiver<WorkerMessage>) {
+    info!("Starting records worker for guild {guild_id}...");
 
-    let mut open = false;
+    let query_agent = DBQueryAgent::new(pool.clone(), guild_id);
+    let manager = DBManager::new(pool, guild_id);
 
-    let opening_time = NaiveTime::from_hms_opt(6, 0, 0).unwrap();
-    let closing_time = NaiveTime::from_hms_opt(18, 0, 30).unwrap();
+    let mut config = load_market_config(&query_agent).await;
 
-    let mut opening_data: HashMap<i64, CurrencyData> = HashMap::new();
-    let mut closing_data: HashMap<i64, CurrencyData> = HashMap::new();
+    // The session being traded, None while the market is closed. Picked back up from the database after a restart
+    let mut session = loop {
+        match query_agent.get_open_session().await {
+            Ok(session) => break session,
+            Err(e) => {
+                error!("Couldn't check for a session in progress: {e:?}");
+                sleep(Duration::seconds(RETRY_DELAY_SECONDS).to_std().unwrap()).await;
+            }
+        }
+    };
+    if let Some(session) = &session {
+        info!("Resuming market session for {}, closing at {}", session.record_date, session.closes_at);
+    }
 
-    let query_agent = DBQueryAgent::new(pool.clone());
-    let manager = DBManager::new(pool);
+    // Stops the session that just closed from being reopened if the clock is slightly behind the timer
+    let mut last_close: Option<DateTime<Utc>> = None;
+    let mut last_snapshot = Utc::now();
+    // Set by `WorkerMessage::ForceOpen` until the session has been opened
+    let mut forced_open: Option<MarketSession> = None;
+    let mut listening = true;
 
     loop {
-        if let Ok(Some(message)) = rx.try_next() {
-            match message {
-                WorkerMessage::Halt => {
-                    warn!("Halting worker 'record'...");
-                    rx.close();
-                    return
+        let now = Utc::now();
+        let next_event = match &session {
+            Some(session) => {
+                let next_snapshot = last_snapshot + Duration::minutes(config.snapshot_interval_minutes as i64);
+                if config.snapshot_interval_minutes > 0 && next_snapshot < session.closes_at {
+                    Some((MarketEvent::Snapshot, next_snapshot))
+                } else {
+                    Some((MarketEvent::Close, session.closes_at))
                 }
+            },
+            None => forced_open.or_else(|| next_opening(&config, now, last_close))
+                .map(|session| (MarketEvent::Open(session), session.opens_at))
+        };
+
+        let wait = match &next_event {
+            Some((_, at)) => (*at - now).to_std().unwrap_or_default(),
+            None => {
+                warn!("No trading days are configured, the market will stay closed");
+                Duration::hours(IDLE_HOURS).to_std().unwrap()
             }
-        }
-        let now = Utc::now().time();
-        if now > opening_time && now < closing_time && !open {
-            open = true;
-            match query_agent.list_currencies(200, crate::commands::query::CurrencySort::CurrencyCode).await {
-                Ok(data) => {
-                    for currency in data {
-                        opening_data.insert(currency.currency_id, currency);
-                    };
-                    info!("Logged data at opening!");
-                    info!("Time now: {now:?}");
+        };
+
+        // Wait for the next opening, snapshot or closing, waking early if a message arrives
+        let message = if listening {
+            match timeout(wait, rx.next()).await {
+                Ok(Some(message)) => Some(message),
+                Ok(None) => {
+                    listening = false;
+                    continue
                 },
-                Err(e) => {
-                    error!("Couldn't get currency data at opening: {e:?}");
-                    continue;
-                }
+                Err(_) => None
             }
-        }
+        } else {
+            sleep(wait).await;
+            None
+        };
+
+        match message {
+            Some(WorkerMessage::Halt) => {
+                warn!("Halting worker 'record'...");
+                rx.close();
+                return
+            },
+            Some(WorkerMessage::Reschedule) => {
+                config = load_market_config(&query_agent).await;
+                info!("Rescheduled market: open {} to {} {} on {}", config.open_time, config.close_time, config.timezone, config.describe_days());
 
-        if now > closing_time && open {
-            open = false;
-            match query_agent.list_currencies(200, crate::commands::query::CurrencySort::CurrencyCode).await {
-                Ok(data) => {
-                    for currency in data {
-                        closing_data.insert(currency.currency_id, currency);
-                    };
-                    info!("Logged data at closing!");
-                    info!("Time now: {now:?}");
+                // A session in progress closes when the new hours say it should, or straight away if it shouldn't be open at all
+                if let Some(session) = &mut session {
+                    let now = Utc::now();
+                    session.closes_at = config.next_session(now)
+                        .filter(|next| next.opens_at <= now)
+                        .map_or(now, |next| next.closes_at);
+                    if let Err(e) = manager.reschedule_session(session.session_id, session.closes_at).await {
+                        error!("Couldn't store new closing time for the session in progress: {e:?}");
+                    }
                 }
-                Err(e) => {
-                    error!("Couldn't get currency data at closing: {e:?}");
-                    continue;
+                continue
+            },
+            Some(WorkerMessage::ForceOpen) => {
+                let now = Utc::now();
+                match (&session, config.next_session(now)) {
+                    (Some(_), _) => warn!("Ignoring request to open the market, it is already open"),
+                    (None, Some(next)) => {
+                        info!("Opening the market early, until {}", next.closes_at);
+                        forced_open = Some(MarketSession { opens_at: now, closes_at: next.closes_at });
+                    },
+                    (None, None) => warn!("Ignoring request to open the market, no trading days are configured so it would never close")
                 }
-            };
-
-            for (_id, currency) in opening_data.clone() {
-                if !closing_data.contains_key(&currency.currency_id) {
-                    continue
+                continue
+            },
+            Some(WorkerMessage::ForceClose) => {
+                match &mut session {
+                    Some(session) => {
+                        info!("Closing the market early");
+                        session.closes_at = Utc::now();
+                        if let Err(e) = manager.reschedule_session(session.session_id, session.closes_at).await {
+                            error!("Couldn't store new closing time for the session in progress: {e:?}");
+                        }
+                    },
+                    None => warn!("Ignoring request to close the market, it is already closed")
                 }
-                let record = match manager.insert_record(currency.currency_id, currency.value, closing_data.get(&currency.currency_id).unwrap().value).await {
-                    Ok(data) => {
-                        info!("Inserted record for {currency:?}");
-                        data
+                continue
+            },
+            Some(WorkerMessage::RebuildRecords(record_date)) => {
+                rebuild_records(&query_agent, &manager, &config, session.as_ref(), record_date).await;
+                continue
+            },
+            None => {}
+        }
+
+        match next_event {
+            Some((MarketEvent::Open(next), _)) => {
+                let record_date = next.opens_at.with_timezone(&config.tz()).date_naive();
+                backfill_missed_days(&query_agent, &manager, &config, record_date).await;
+
+                match manager.open_session(record_date, next.closes_at).await {
+                    Ok(opened) => {
+                        info!("Logged data at opening, market closes at {}", opened.closes_at);
+                        session = Some(opened);
+                        forced_open = None;
+                        last_snapshot = Utc::now();
                     },
                     Err(e) => {
-                        error!("Couldn't get result of insert command: error: {e:?}");
-                        continue;
+                        error!("Couldn't store opening data: {e:?}");
+                        sleep(Duration::seconds(RETRY_DELAY_SECONDS).to_std().unwrap()).await;
                     }
+                }
+            },
+            Some((MarketEvent::Snapshot, _)) => {
+                // A failed snapshot is skipped rather than retried, there will be another one soon
+                match manager.take_snapshots().await {
+                    Ok(count) => info!("Took snapshots of {count} currencies"),
+                    Err(e) => error!("Couldn't take snapshots: {e:?}")
+                }
+                last_snapshot = Utc::now();
+            },
+            Some((MarketEvent::Close, _)) => {
+                let Some(closing_session) = &session else {
+                    continue
                 };
 
-                match query_agent.get_reports(14, currency.currency_code.clone()).await {
-                    Ok(data) => {
-                        let filename = format!("data/{:05}.png", currency.currency_id);
-                        {
-                            //let root = BitMapBackend::with_buffer(&mut buffer, (1024, 768)).into_drawing_area();
-							let root = BitMapBackend::new(filename.as_str(), (1024, 768)).into_drawing_area();
-                            let bg_color = RGBColor(56, 58, 64);
-                            root.fill(&bg_color).unwrap();
-                            let latest_data = data.get(0).unwrap();
-                            let (to_date, from_date) = (
-                                latest_data.record_date,
-                                data.get(data.len() - 1).unwrap().record_date
-                            );
-
-                            let graph_color;
-                            if let Some(prev_data) = data.get(1) {
-                                let value_difference = latest_data.closing_value - prev_data.closing_value;
-                                info!("Currency value change since last record: {value_difference:.5}");
-                                if value_difference > 0.2 {
-                                    graph_color = &LIME_A700;
-                                } else if value_difference < -0.2 {
-                                    graph_color = &RED_600;
-                                } else { 
-                                    graph_color = &BLUEGREY_A100;
-                                }
-                            } else {
-                                graph_color = &BLUEGREY_A100;
-                            }
-
-                            let mut max_value: f64 = 0.0;
-                            for record in data.clone() {
-                                if record.closing_value > max_value { max_value = record.closing_value }
-                            }
-
-                            max_value += 1.0;
-
-                            let mut chart = ChartBuilder::on(&root)
-                                .margin(10)
-                                .caption(format!("Currency trend for {}", currency.currency_name), ("sans-serif", 40, &GREY_50))
-                                .set_label_area_size(LabelAreaPosition::Left, 60)
-                                .set_label_area_size(LabelAreaPosition::Right, 60)
-                                .set_label_area_size(LabelAreaPosition::Bottom, 40)
-                                .build_cartesian_2d(from_date..to_date, 0f64..max_value)
-                                .unwrap();
-
-                            chart
-                                .configure_mesh()
-                                .disable_x_mesh()
-                                .disable_y_mesh()
-                                .x_labels(30)
-                                .max_light_lines(4)
-                                .y_desc(format!("Currency value (gold ingots per {})", currency.currency_code))
-                                .axis_desc_style(("sans-serif", 30, &GREY_50))
-                                .x_label_style(("sans-serif", 20, &GREY_50))
-                                .y_label_style(("sans-serif", 20, &GREY_50))
-                                .axis_style(&GREY_50)
-                                .draw()
-                                .unwrap();
-
-
-                            chart
-                                .draw_series(
-                                    LineSeries::new(
-                                    data.iter().map(|record| {
-                                        (record.record_date, record.closing_value)
-                                    }), 
-                                    graph_color
-                                    )
-                                )
-                                .unwrap();
-
-                            root.present().expect("Error generating graph!");
-                        }
+                // Keeps the closing values among the snapshots, so the day can be rebuilt from them
+                if let Err(e) = manager.take_snapshots().await {
+                    error!("Couldn't take snapshots at closing: {e:?}");
+                }
 
-                        let client = reqwest::Client::new();
-                        let file_stream = std::fs::read(filename.clone()).unwrap();
-                        let file_part = reqwest::multipart::Part::bytes(file_stream)
-                            .file_name(filename)
-                            .mime_str("image/jpg")
-                            .unwrap();
-                        let form = reqwest::multipart::Form::new()
-                            .part("file", file_part);
-
-                        info!("Sending post request...");
-                        let req = client.
-                            post(format!("https://economist-image-server.shuttleapp.rs/{:05}/{:05}", currency.currency_id, record.record_id))
-                            .multipart(form);
-                        warn!("Generated request {req:?}");
-                        match req
-                            .send()
-                            .await {
-                                Ok(res) => info!("Send HTTP POST, got response: {res:?}"),
-                                Err(e) => error!("Got error trying to post file: {e:?}")
-                            };
+                let late = Utc::now() - closing_session.closes_at > Duration::minutes(LATE_CLOSE_MINUTES);
+                if late {
+                    warn!("Closing the session for {} late, its records will be flagged as interpolated", closing_session.record_date);
+                }
+
+                match manager.close_session(closing_session, late).await {
+                    Ok(count) => info!("Logged {count} records at closing!"),
+                    Err(e) => {
+                        error!("Couldn't write records at closing: {e:?}");
+                        sleep(Duration::seconds(RETRY_DELAY_SECONDS).to_std().unwrap()).await;
+                        continue
                     }
-                    Err(e) => warn!("Caught an error while looking up records for currency `{}`: {e}", currency.currency_code)
                 }
 
-            }
-            info!("Logged records!");
+                post_closing(&http, &query_agent, closing_session.record_date).await;
+                last_close = Some(closing_session.closes_at);
+                session = None;
+            },
+            None => {}
+        }
+    }
+}
+
+// The ledger always gets the full summary, whether or not the market report has a channel of its own
+async fn post_closing(http: &Http, query_agent: &DBQueryAgent, record_date: NaiveDate) {
+    let records = match query_agent.get_records_on(record_date).await {
+        Ok(r) => r,
+        Err(e) => {
+            error!("Couldn't get the records for {record_date} to report on: {e:?}");
+            return
+        }
+    };
+
+    let currencies = query_agent.stream_active_currencies()
+        .try_fold(HashMap::new(), |mut currencies, currency| async move {
+            currencies.insert(currency.currency_id, currency);
+            Ok(currencies)
+        })
+        .await;
+    let mut currencies: HashMap<i64, CurrencyData> = match currencies {
+        Ok(c) => c,
+        Err(e) => {
+            error!("Couldn't get currencies to report on {record_date}: {e:?}");
+            return
+        }
+    };
+
+    let days: Vec<(CurrencyData, RecordData)> = records.into_iter()
+        .filter_map(|record| currencies.remove(&record.currency_id).map(|currency| (currency, record)))
+        .collect();
+
+    ledger::post(http, query_agent, ledger::daily_summary(record_date, days.clone())).await;
+
+    // The report is still worth posting without its chart
+    let chart = match render_report_chart(query_agent, &days, record_date).await {
+        Ok(c) => c,
+        Err(e) => {
+            error!("Couldn't render the market report chart for {record_date}: {e}");
+            None
+        }
+    };
+
+    ledger::post_report(http, query_agent, ledger::market_report(record_date, days), chart).await;
+}
+
+// Charts the recent history of the currencies that moved the most, None if nothing moved
+async fn render_report_chart(query_agent: &DBQueryAgent, days: &[(CurrencyData, RecordData)], record_date: NaiveDate) -> anyhow::Result<Option<Vec<u8>>> {
+    let mut movers: Vec<&(CurrencyData, RecordData)> = days.iter().filter(|(_, record)| record.delta_value != 0.0).collect();
+    movers.sort_by(|(_, a), (_, b)| b.delta_value.abs().total_cmp(&a.delta_value.abs()));
+
+    let since = record_date - Duration::days(REPORT_CHART_DAYS);
+    let mut series = vec![];
+    for (currency, _) in movers.into_iter().take(REPORT_CHART_CURRENCIES) {
+        let records = query_agent.get_reports(None, currency.currency_code.clone(), Some(since)).await?;
+        series.push((currency.clone(), records));
+    }
+    if series.is_empty() {
+        return Ok(None)
+    }
+
+    let png = task::spawn_blocking(move || charts::render_market_report(&series, ChartTheme::Dark)).await??;
+    Ok(Some(png))
+}
+
+async fn load_market_config(query_agent: &DBQueryAgent) -> MarketConfig {
+    match query_agent.get_market_config().await {
+        Ok(config) => config,
+        Err(e) => {
+            error!("Couldn't load market hours, using the defaults: {e:?}");
+            MarketConfig::new(query_agent.guild_id())
+        }
+    }
+}
+
+// The next session to open. A session that was closed early by hand isn't reopened
+fn next_opening(config: &MarketConfig, now: DateTime<Utc>, last_close: Option<DateTime<Utc>>) -> Option<MarketSession> {
+    let next = config.next_session(last_close.map_or(now, |close| close.max(now)))?;
+    match last_close {
+        Some(close) if next.opens_at <= close => config.next_session(next.closes_at),
+        _ => Some(next)
+    }
+}
+
+async fn rebuild_records(query_agent: &DBQueryAgent, manager: &DBManager, config: &MarketConfig, open_session: Option<&SessionData>, record_date: NaiveDate) {
+    if open_session.is_some_and(|session| session.record_date == record_date) {
+        warn!("Not rebuilding records for {record_date}, the market is still open");
+        return
+    }
+
+    // Use when the session actually ran if it was stored, otherwise when it should have
+    let bounds = match query_agent.get_session(record_date).await {
+        Ok(Some(session)) => Some((session.opened_at, session.closed_at.unwrap_or(session.closes_at))),
+        Ok(None) => config.session_on(record_date).map(|session| (session.opens_at, session.closes_at)),
+        Err(e) => {
+            error!("Couldn't look up the session for {record_date}: {e:?}");
+            return
+        }
+    };
+    let Some((from, to)) = bounds else {
+        warn!("Not rebuilding records for {record_date}, the market doesn't trade on that day");
+        return
+    };
+
+    match manager.rebuild_records(record_date, from, to).await {
+        Ok(count) => info!("Rebuilt {count} records for {record_date}"),
+        Err(e) => error!("Couldn't rebuild records for {record_date}: {e:?}")
+    }
+}
+
+// Fills in records for trading days the bot was offline for, moving each currency in a straight line from its last record to its value now
+async fn backfill_missed_days(query_agent: &DBQueryAgent, manager: &DBManager, config: &MarketConfig, until: NaiveDate) {
+    let latest_records = match query_agent.get_latest_records().await {
+        Ok(r) => r,
+        Err(e) => {
+            error!("Couldn't check for missed records: {e:?}");
+            return
+        }
+    };
+
+    let values = query_agent.stream_active_currencies()
+        .try_fold(HashMap::new(), |mut values, currency| async move {
+            values.insert(currency.currency_id, currency.value);
+            Ok(values)
+        })
+        .await;
+    let values: HashMap<i64, f64> = match values {
+        Ok(v) => v,
+        Err(e) => {
+            error!("Couldn't get currency values to backfill missed records: {e:?}");
+            return
+        }
+    };
+    let mut missed = vec![];
+    for latest in latest_records {
+        let Some(current_value) = values.get(&latest.currency_id) else {
+            continue
         };
-        sleep(chrono::Duration::seconds(10).to_std().unwrap()).await;
+
+        let total_days = (until - latest.record_date).num_days() as f64;
+        let mut opening_value = latest.closing_value;
+        let mut date = latest.record_date + Duration::days(1);
+        while date < until {
+            if config.trades_on(date.weekday()) {
+                let elapsed_days = (date - latest.record_date).num_days() as f64;
+                let closing_value = latest.closing_value + (current_value - latest.closing_value) * elapsed_days / total_days;
+                missed.push(RecordData {
+                    record_date: date,
+                    currency_id: latest.currency_id,
+                    opening_value,
+                    closing_value,
+                    interpolated: true,
+                    ..RecordData::default()
+                });
+                opening_value = closing_value;
+            }
+            date += Duration::days(1);
+        }
+    }
+
+    if missed.is_empty() {
+        return
+    }
+
+    match manager.insert_interpolated_records(&missed).await {
+        Ok(_) => info!("Backfilled {} records for days missed while offline", missed.len()),
+        Err(e) => error!("Couldn't backfill missed records: {e:?}")
     }
 }